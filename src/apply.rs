@@ -0,0 +1,1423 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::time::Instant;
+
+use healthchecks::errors::HealthchecksApiError;
+use healthchecks::manage::{ManageClient, UpsertResult};
+use healthchecks::model::{Check, NewCheck, UpdatedCheck};
+use json_patch::{AddOperation, PatchOperation, RemoveOperation, ReplaceOperation};
+use k8s_openapi::api::batch::v1::CronJob;
+use k8s_openapi::api::core::v1::{Container, EnvVar};
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::ObjectMeta;
+use kube::api::{Patch, PatchParams, PostParams};
+
+use crate::{Args, Projects, ReplaceStrategy};
+use crate::error::SyncError;
+use crate::output::{say, warning};
+use crate::plan::{NamespacePlan, PlannedJob, SkipReason};
+use crate::style::Status;
+use crate::summary::{Action, Outcome, Summary, Tally};
+
+/// Upserts the planned checks, and updates the CronJobs to reference them.
+///
+/// Under --dry-run, it'll only print what it would have done.
+pub async fn apply(
+	args: &Args,
+	projects: &Projects,
+	plans: Vec<NamespacePlan>,
+	color: bool,
+	summary: &mut Summary,
+) -> Result<(), SyncError> {
+	let Args {
+		dry_run,
+		verbose,
+		rank,
+		ref env_key,
+		ref env_value_template,
+		ref remove_envs,
+		..
+	} = *args;
+
+	let text = args.output == crate::OutputFormat::Text && !args.summary_only;
+
+	// The existing checks tell which ones are already up to date, and under --key-by-uid, which id they have.
+	// They're fetched even for a dry-run, to tell what would happen, unless they'd all have been cleared first.
+	let mut checks = if !dry_run || !args.hc.clear_existing_checks {
+		existing_checks(args, projects, &plans).await.map_err(SyncError::Checks)?
+	} else {
+		HashMap::new()
+	};
+	let by_name = !args.hc.no_unique && args.hc.unique_by.contains(&crate::UniqueKey::Name);
+
+	// The projects that have run into their check limit, so nothing more is created in them (but updates carry on).
+	let mut limited: HashSet<Option<String>> = HashSet::new();
+
+	let mut context = None;
+
+	for mut plan in plans {
+		if text {
+			print_header(&plan, &mut context);
+		}
+		note_empty(args, text, &plan, summary);
+		let mut tally = Tally::default();
+
+		for (name, reason) in &plan.skipped {
+			let outcome = outcome(&plan.context, &plan.namespace, name, None, Action::Skipped { reason: *reason });
+			conclude(text, color, summary, &mut tally, outcome);
+		}
+
+		if text && dry_run && verbose > 0 {
+			say!("\t\tSegment frequencies:");
+			for (segment, count) in &plan.frequencies {
+				let marker = if *count >= rank { " (tag)" } else { "" };
+				say!("\t\t\t{: <30} {}{}", segment, count, marker);
+			}
+		}
+
+		if verbose >= crate::trace::TRACE_LEVEL {
+			let line = format!("Trace: {}:{} uses {}", plan.context, plan.namespace, projects.describe(&plan));
+			eprintln!("{}", crate::redact::redact(&line, &args.hc.secrets()));
+		}
+		let scope = projects.scope(&plan);
+		let client = projects.client(&plan);
+
+		// Its jobs are going away, so rather than alert during the teardown, the checks are paused (and left for later cleanup).
+		if plan.terminating {
+			warning!("{}:{} is being deleted, so its checks are paused instead of synchronised", plan.context, plan.namespace);
+			if dry_run {
+				for planned in &plan.jobs {
+					if text {
+						say!("\t\t: {: <50} -> Would pause", planned.name());
+					}
+					tally.add(&Action::Paused);
+					summary.record(outcome(&plan.context, &plan.namespace, planned.name(), None, Action::Paused));
+				}
+			} else if let Some(client) = client {
+				let existing = checks.get(&scope).map(Vec::as_slice).unwrap_or_default();
+				pause_jobs(args, color, &plan, client, existing, summary, &mut tally).await;
+			}
+			print_tally(args, &plan, &tally);
+			continue;
+		}
+
+		for planned in &mut plan.jobs {
+			let name = planned.check.name.clone().unwrap_or_default();
+
+			let existing = checks.get(&scope).map(Vec::as_slice).unwrap_or_default();
+			let identity = crate::plan::identity_tag(args, &planned.job);
+			let known = find_check(existing, identity.as_deref(), &name, by_name);
+			if let (true, Some(check)) = (args.hc.append_channels, known) {
+				planned.check.channels = append_channels(planned.check.channels.take(), check.channels.as_deref());
+			}
+			let up_to_date = known.is_some_and(|check| crate::plan::is_up_to_date(&planned.check, check));
+			let exists = known.is_some() || (identity.is_none() && existing.iter().any(|check| check.name == name));
+
+			if dry_run {
+				let action = match (up_to_date, exists) {
+					(true, _) => Action::Unchanged,
+					(false, true) => Action::Updated,
+					(false, false) => Action::Created,
+				};
+				let check = known.and_then(Check::id);
+				tally.add(&action);
+				summary.record(outcome(&plan.context, &plan.namespace, &name, check, action.clone()));
+				if !text {
+					continue;
+				}
+
+				let tags = planned.check.tags.as_deref().unwrap_or_default();
+				say!("\t\t: {: <50} -> {}[{}]", name, action.status().paint(color), tags);
+
+				// The exact check that would be sent, to catch e.g. an empty channel or a mis-split tag.
+				if verbose > 0 {
+					for line in check_lines(&planned.check) {
+						say!("\t\t\t{}", line);
+					}
+				}
+
+				if let Ok((_, _, containers)) = crate::plan::describe(&mut planned.job) {
+					let stale = stale_env(containers, remove_envs);
+					if !stale.is_empty() {
+						say!("\t\t\tWould remove env: {}", stale.join(", "));
+					}
+				}
+				continue;
+			}
+			let Some(client) = client else {
+				continue;
+			};
+
+			if !exists && limited.contains(&scope) {
+				let outcome = outcome(&plan.context, &plan.namespace, &name, None, Action::Skipped { reason: SkipReason::CheckLimit });
+				conclude(text, color, summary, &mut tally, outcome);
+				continue;
+			}
+
+			if !up_to_date {
+				let verb = if exists { "Update" } else { "Create" };
+				if !crate::confirmed(args, &format!("{} \"{}\"?", verb, name)) {
+					let outcome = outcome(&plan.context, &plan.namespace, &name, None, Action::Skipped { reason: SkipReason::Declined });
+					conclude(text, color, summary, &mut tally, outcome);
+					continue;
+				}
+			}
+
+			let result = match known {
+				Some(check) if up_to_date => Ok((Action::Unchanged, check.clone())),
+				Some(check) if identity.is_some() => {
+					let Some(check_id) = check.id() else {
+						continue;
+					};
+					let path = format!("checks/{}", check_id);
+					let updated = updated_check(&planned.check);
+					crate::trace::hc(args, client, "POST", &path, move |client| client.update_check(updated, &check_id))
+						.await
+						.map(|check| (Action::Updated, check))
+				}
+				_ => {
+					// No check carries its identity tag yet, so it mustn't take over another job's check of the same name.
+					let mut check = planned.check.clone();
+					if identity.is_some() && check.unique.is_some() {
+						check.unique = Some(vec!["tags".into()]);
+					}
+					crate::trace::hc(args, client, "POST", "checks/", move |client| client.upsert_check(check))
+						.await
+						.map(|(status, check)| {
+							let action = match status {
+								UpsertResult::Created => Action::Created,
+								UpsertResult::Updated => Action::Updated,
+							};
+							(action, check)
+						})
+				}
+			};
+			let (action, check) = match result {
+				Ok(result) => result,
+				Err(err) => {
+					if !exists && matches!(err, HealthchecksApiError::CheckLimitReached) && limited.insert(scope.clone()) {
+						warning!(
+							"The HealthChecks project used by {}:{} has reached its check limit, so no more checks are created in it (existing ones are still updated)",
+							plan.context,
+							plan.namespace,
+						);
+					}
+					let error = crate::redact::redact(&err.to_string(), &args.hc.secrets());
+					conclude(text, color, summary, &mut tally, outcome(&plan.context, &plan.namespace, &name, None, Action::Failed { error }));
+					continue;
+				}
+			};
+			let Some(check_id) = check.id() else {
+				continue;
+			};
+
+			// Later jobs with the same identity (e.g. a shared external id) must find this check, rather than create another.
+			let existing = checks.entry(scope.clone()).or_default();
+			match existing.iter_mut().find(|existing| existing.id().as_ref() == Some(&check_id)) {
+				Some(existing) => *existing = check.clone(),
+				None => existing.push(check.clone()),
+			}
+
+			let touched = matches!(action, Action::Created | Action::Updated);
+			conclude(text, color, summary, &mut tally, outcome(&plan.context, &plan.namespace, &name, Some(check_id.clone()), action));
+			if args.verify && touched {
+				verify(args, client, &name, &check_id, &planned.check).await;
+			}
+
+			let mut injections = vec![];
+			if let Some(env_key) = env_key.as_deref() {
+				let value = env_value(env_value_template, &check_id, check.ping_url.as_deref().unwrap_or_default());
+				injections.push((env_key, value));
+			}
+			if let (Some(start_key), Some(ping_url)) = (args.start_url_env.as_deref(), check.ping_url.as_deref()) {
+				injections.push((start_key, start_url(ping_url)));
+			}
+
+			// Skip updating kubernetes, if there's nothing to change in the env (or the pod annotation).
+			if injections.is_empty() && remove_envs.is_empty() && args.pod_annotation.is_none() {
+				continue;
+			}
+
+			let Ok((job, _, containers)) = crate::plan::describe(&mut planned.job) else {
+				continue;
+			};
+
+			let mut ops = vec![];
+			remove_env(containers, remove_envs, &mut ops);
+			if !injections.is_empty() {
+				let overwrite = if args.env_key_if_missing_only {
+					Overwrite::Never
+				} else if args.overwrite_value_from {
+					Overwrite::All
+				} else {
+					Overwrite::Values
+				};
+				let missing: Vec<_> = args.env_containers.iter()
+					.filter(|name| !containers.iter().any(|container| &container.name == *name))
+					.cloned()
+					.collect();
+				if !missing.is_empty() {
+					if !args.allow_missing_env_containers {
+						return Err(SyncError::MissingContainers {
+							job: job.into(),
+							namespace: plan.namespace.clone(),
+							containers: missing,
+						});
+					}
+					warning!("\"{}\" has no container named {}", job, missing.join(", "));
+				}
+				let duplicates = duplicate_containers(containers);
+				if !duplicates.is_empty() {
+					warning!("\"{}\" repeats the container(s) {}, so only the first of each is injected", job, duplicates.join(", "));
+				}
+
+				for (key, value) in &injections {
+					let externally_managed = inject_env(containers, &args.env_containers, key, value, overwrite, &mut ops);
+					if !externally_managed.is_empty() {
+						warning!(
+							"\"{}\" sets {} through valueFrom in {}, so it's left alone [Use \"--overwrite-value-from\" to replace it]",
+							job,
+							key,
+							externally_managed.join(", "),
+						);
+					}
+				}
+			}
+
+			if let Some(key) = args.pod_annotation.as_deref() {
+				annotate_pod(&mut planned.job, key, &check_id, &mut ops);
+			}
+
+			write_env(args, &plan.api, &plan.namespace, &planned.job, ops).await?;
+		}
+
+		print_tally(args, &plan, &tally);
+	}
+
+	Ok(())
+}
+
+/// Removes the --env-key variable from every planned job, without touching HealthChecks.
+///
+/// Under --dry-run, it'll only print what it would have removed.
+pub async fn remove_managed_env(
+	args: &Args,
+	plans: Vec<NamespacePlan>,
+	color: bool,
+	summary: &mut Summary,
+) -> Result<(), SyncError> {
+	let Some(env_key) = args.env_key.clone() else {
+		return Ok(());
+	};
+	let keys: Vec<_> = std::iter::once(env_key).chain(args.start_url_env.clone()).collect();
+	let text = args.output == crate::OutputFormat::Text && !args.summary_only;
+
+	let mut context = None;
+
+	for mut plan in plans {
+		if text {
+			print_header(&plan, &mut context);
+		}
+		note_empty(args, text, &plan, summary);
+		let mut tally = Tally::default();
+
+		for planned in &mut plan.jobs {
+			let Ok((name, _, containers)) = crate::plan::describe(&mut planned.job) else {
+				continue;
+			};
+			if stale_env(containers, &keys).is_empty() {
+				continue;
+			}
+
+			if args.dry_run {
+				if text {
+					say!("\t\t: {: <50} -> Would remove env: {}", name, keys.join(", "));
+				}
+				tally.add(&Action::Updated);
+				summary.record(outcome(&plan.context, &plan.namespace, name, None, Action::Updated));
+				continue;
+			}
+
+			let name = name.to_string();
+			let mut ops = vec![];
+			remove_env(containers, &keys, &mut ops);
+			write_env(args, &plan.api, &plan.namespace, &planned.job, ops).await?;
+
+			if text {
+				say!("\t\t: {: <50} -> {}(\"{}\")", name, Status::Updated.paint(color), keys.join(", "));
+			}
+			tally.add(&Action::Updated);
+			summary.record(outcome(&plan.context, &plan.namespace, &name, None, Action::Updated));
+		}
+
+		print_tally(args, &plan, &tally);
+	}
+
+	Ok(())
+}
+
+/// Pauses the existing check of every planned job, e.g. for a maintenance window.
+///
+/// Under --dry-run, it'll only print which checks it would pause.
+pub async fn pause(
+	args: &Args,
+	projects: &Projects,
+	plans: Vec<NamespacePlan>,
+	color: bool,
+	summary: &mut Summary,
+) -> Result<(), SyncError> {
+	let text = args.output == crate::OutputFormat::Text && !args.summary_only;
+	// Even a dry-run needs them, to tell which checks would be paused.
+	let checks = existing_checks(args, projects, &plans).await.map_err(SyncError::Checks)?;
+
+	let mut context = None;
+
+	for plan in plans {
+		if text {
+			print_header(&plan, &mut context);
+		}
+		note_empty(args, text, &plan, summary);
+		let mut tally = Tally::default();
+
+		if let Some(client) = projects.client(&plan) {
+			let existing = checks.get(&projects.scope(&plan)).map(Vec::as_slice).unwrap_or_default();
+			pause_jobs(args, color, &plan, client, existing, summary, &mut tally).await;
+		}
+
+		print_tally(args, &plan, &tally);
+	}
+
+	Ok(())
+}
+
+/// Pauses the existing check of every job in the namespace, or under --dry-run, prints which ones it would pause.
+async fn pause_jobs(
+	args: &Args,
+	color: bool,
+	plan: &NamespacePlan,
+	client: &ManageClient,
+	existing: &[Check],
+	summary: &mut Summary,
+	tally: &mut Tally,
+) {
+	let text = args.output == crate::OutputFormat::Text && !args.summary_only;
+	let by_name = !args.hc.no_unique && args.hc.unique_by.contains(&crate::UniqueKey::Name);
+
+	for planned in &plan.jobs {
+		let name = planned.name();
+		let identity = crate::plan::identity_tag(args, &planned.job);
+		// A job without a check has nothing to pause.
+		let Some(check) = find_check(existing, identity.as_deref(), name, by_name) else {
+			continue;
+		};
+		let Some(check_id) = check.id() else {
+			continue;
+		};
+
+		if check.status == "paused" {
+			conclude(text, color, summary, tally, outcome(&plan.context, &plan.namespace, name, Some(check_id), Action::Unchanged));
+			continue;
+		}
+		if args.dry_run {
+			if text {
+				say!("\t\t: {: <50} -> Would pause(\"{}\")", name, check_id);
+			}
+			tally.add(&Action::Paused);
+			summary.record(outcome(&plan.context, &plan.namespace, name, Some(check_id), Action::Paused));
+			continue;
+		}
+		if !crate::confirmed(args, &format!("Pause \"{}\"?", name)) {
+			let outcome = outcome(&plan.context, &plan.namespace, name, None, Action::Skipped { reason: SkipReason::Declined });
+			conclude(text, color, summary, tally, outcome);
+			continue;
+		}
+
+		let path = format!("checks/{}/pause", check_id);
+		let id = check_id.clone();
+		let action = match crate::trace::hc(args, client, "POST", &path, move |client| client.pause(&id)).await {
+			Ok(_) => Action::Paused,
+			Err(err) => Action::Failed {
+				error: crate::redact::redact(&err.to_string(), &args.hc.secrets()),
+			},
+		};
+		conclude(text, color, summary, tally, outcome(&plan.context, &plan.namespace, name, Some(check_id), action));
+	}
+}
+
+/// The existing checks of every project used by the plans, fetched once per project.
+pub async fn existing_checks(args: &Args, projects: &Projects, plans: &[NamespacePlan]) -> Result<HashMap<Option<String>, Vec<Check>>, HealthchecksApiError> {
+	let mut checks = HashMap::new();
+	for (scope, client) in projects.used_by(plans) {
+		let fetched = crate::trace::hc(args, client, "GET", "checks", |client| client.get_checks()).await?;
+		checks.insert(scope, fetched);
+	}
+	Ok(checks)
+}
+
+/// The existing check of a job: the one carrying its identity tag, or (when checks are unique by name) the one with its name.
+fn find_check<'a>(existing: &'a [Check], identity: Option<&str>, name: &str, by_name: bool) -> Option<&'a Check> {
+	match identity {
+		Some(tag) => existing.iter().find(|check| check.tags.split_whitespace().any(|existing| existing == tag)),
+		None if by_name => existing.iter().find(|check| check.name == name),
+		None => None,
+	}
+}
+
+/// The existing check that `job` would update, out of those in its project.
+pub fn existing_check<'a>(args: &Args, existing: &'a [Check], job: &PlannedJob) -> Option<&'a Check> {
+	let by_name = !args.hc.no_unique && args.hc.unique_by.contains(&crate::UniqueKey::Name);
+	let identity = crate::plan::identity_tag(args, &job.job);
+	find_check(existing, identity.as_deref(), job.name(), by_name)
+}
+
+fn outcome(context: &str, namespace: &str, name: &str, check: Option<String>, action: Action) -> Outcome {
+	Outcome {
+		context: Some(context.into()),
+		namespace: Some(namespace.into()),
+		name: name.into(),
+		check,
+		action,
+	}
+}
+
+/// Records the outcome, and under --output text, prints it along with the check (or whatever explains it).
+fn conclude(text: bool, color: bool, summary: &mut Summary, tally: &mut Tally, outcome: Outcome) {
+	tally.add(&outcome.action);
+	if text {
+		let detail = match &outcome.action {
+			Action::Skipped { reason } => reason.to_string(),
+			Action::Failed { error } => error.clone(),
+			_ => outcome.check.clone().unwrap_or_default(),
+		};
+		say!("\t\t: {: <50} -> {}(\"{}\")", outcome.name, outcome.action.status().paint(color), detail);
+	}
+	summary.record(outcome);
+}
+
+/// Prints the namespace's single line under --summary-only.
+fn print_tally(args: &Args, plan: &NamespacePlan, tally: &Tally) {
+	if args.summary_only && args.output == crate::OutputFormat::Text {
+		say!("{}/{}: {}", plan.context, plan.namespace, tally);
+	}
+}
+
+fn print_header(plan: &NamespacePlan, context: &mut Option<String>) {
+	if context.as_ref() != Some(&plan.context) {
+		say!("Context: {}", plan.context);
+		*context = Some(plan.context.clone());
+	}
+	say!("\tNamespace: {}", plan.namespace);
+}
+
+/// Points out a namespace without any matching CronJobs.
+fn note_empty(args: &Args, text: bool, plan: &NamespacePlan, summary: &mut Summary) {
+	let Some(notice) = empty_notice(plan) else {
+		return;
+	};
+	if text {
+		say!("\t\t{}", notice);
+	}
+	if args.warn_empty {
+		warning!("{}:{}: {}", plan.context, plan.namespace, notice);
+	}
+	summary.empty_namespace();
+}
+
+/// Fills in the --env-value-template, with the check's id and ping URL.
+fn env_value(template: &str, check_id: &str, ping_url: &str) -> String {
+	template
+		.replace("{id}", check_id)
+		.replace("{ping_url}", ping_url)
+}
+
+/// The check, as the pretty JSON that's sent for it.
+fn check_lines(check: &NewCheck) -> Vec<String> {
+	serde_json::to_string_pretty(check)
+		.map(|json| json.lines().map(String::from).collect())
+		.unwrap_or_default()
+}
+
+/// The URL a job pings when it starts, so HealthChecks can tell how long it ran.
+fn start_url(ping_url: &str) -> String {
+	format!("{}/start", ping_url.trim_end_matches('/'))
+}
+
+/// The notice for a namespace without any matching CronJobs, telling apart one that has none from one where they were all filtered out.
+fn empty_notice(plan: &NamespacePlan) -> Option<String> {
+	if !plan.jobs.is_empty() || !plan.skipped.is_empty() {
+		return None;
+	}
+
+	Some(match plan.listed {
+		0 => "No matching CronJobs (the namespace has none)".to_string(),
+		listed => format!("No matching CronJobs (all {} were filtered out)", listed),
+	})
+}
+
+/// Re-fetches the check under --verify, and warns about every field that didn't take.
+async fn verify(args: &Args, client: &ManageClient, name: &str, check_id: &str, planned: &NewCheck) {
+	let path = format!("checks/{}", check_id);
+	let id = check_id.to_string();
+	match crate::trace::hc(args, client, "GET", &path, move |client| client.get_check(&id)).await {
+		Ok(actual) => {
+			let fields = crate::plan::mismatches(planned, &actual);
+			if !fields.is_empty() {
+				warning!("\"{}\" (\"{}\") didn't take the planned {}", name, check_id, fields.join(", "));
+			}
+		}
+		Err(err) => {
+			let error = crate::redact::redact(&err.to_string(), &args.hc.secrets());
+			warning!("Unable to verify \"{}\" (\"{}\"): {}", name, check_id, error);
+		}
+	}
+}
+
+/// The planned channels, plus those the existing check already has (e.g. added by hand), under --append-channels.
+///
+/// Without any planned channels, the check's are left alone anyway.
+fn append_channels(planned: Option<String>, existing: Option<&str>) -> Option<String> {
+	let (Some(planned), Some(existing)) = (planned.as_deref(), existing) else {
+		return planned;
+	};
+	let mut channels: Vec<_> = planned.split(',')
+		.chain(existing.split(','))
+		.map(str::trim)
+		.filter(|channel| !channel.is_empty())
+		.collect();
+	channels.sort_unstable();
+	channels.dedup();
+	Some(channels.join(","))
+}
+
+fn updated_check(check: &NewCheck) -> UpdatedCheck {
+	let check = check.clone();
+	UpdatedCheck {
+		name: check.name,
+		tags: check.tags,
+		desc: check.desc,
+		timeout: check.timeout,
+		grace: check.grace,
+		schedule: check.schedule,
+		tz: check.tz,
+		manual_resume: check.manual_resume,
+		channels: check.channels,
+	}
+}
+
+/// Writes the env changes back to the job, as chosen by --replace-strategy.
+///
+/// `job` has to have the changes applied already, and `ops` has to describe them.
+async fn write_env(
+	args: &Args,
+	api: &Option<kube::Api<CronJob>>,
+	namespace: &str,
+	job: &CronJob,
+	ops: Vec<PatchOperation>,
+) -> Result<(), SyncError> {
+	if ops.is_empty() {
+		return Ok(());
+	}
+	// Offline plans have no API, but they're only ever dry-runs.
+	let Some(api) = api else {
+		return Ok(());
+	};
+	let name = job.metadata.name.as_deref().unwrap_or_default();
+
+	let started = Instant::now();
+	let (method, result) = match args.replace_strategy {
+		ReplaceStrategy::Replace => ("PUT", api.replace(name, &PostParams::default(), job).await),
+		ReplaceStrategy::Patch => {
+			let patch: Patch<()> = Patch::Json(json_patch::Patch(ops));
+			("PATCH", api.patch(name, &PatchParams::default(), &patch).await)
+		}
+		ReplaceStrategy::Apply => {
+			let params = PatchParams::apply(&args.field_manager).force();
+			let keys: Vec<_> = args.env_key.iter().chain(&args.start_url_env).map(String::as_str).collect();
+			("PATCH", api.patch(name, &params, &Patch::Apply(env_only(job, &keys, args.pod_annotation.as_deref()))).await)
+		}
+	};
+	let target = format!("/apis/batch/v1/namespaces/{}/cronjobs/{}", namespace, name);
+	crate::trace::log(args, method, "cronjobs", &target, started, &result);
+	result
+		.map_err(|source| SyncError::Patch {
+			job: name.into(),
+			namespace: namespace.into(),
+			source,
+		})?;
+
+	Ok(())
+}
+
+/// Only the `keys` in the containers' env of `job` (and the --pod-annotation), so a server-side apply doesn't claim any of the other fields.
+///
+/// The env is merged by name, so the other variables stay with whoever owns them.
+fn env_only(job: &CronJob, keys: &[&str], pod_annotation: Option<&str>) -> serde_json::Value {
+	let containers: Vec<_> = job.spec.as_ref()
+		.and_then(|spec| spec.job_template.spec.as_ref())
+		.and_then(|spec| spec.template.spec.as_ref())
+		.map(|spec| spec.containers.as_slice())
+		.unwrap_or_default()
+		.iter()
+		.filter_map(|container| {
+			let env: Vec<_> = container.env.iter()
+				.flatten()
+				.filter(|var| keys.contains(&var.name.as_str()))
+				.collect();
+			(!env.is_empty()).then(|| serde_json::json!({
+				"name": container.name,
+				"env": env,
+			}))
+		})
+		.collect();
+
+	let annotations: BTreeMap<_, _> = job.spec.as_ref()
+		.and_then(|spec| spec.job_template.spec.as_ref())
+		.and_then(|spec| spec.template.metadata.as_ref())
+		.and_then(|metadata| metadata.annotations.as_ref())
+		.into_iter()
+		.flatten()
+		.filter(|(key, _)| pod_annotation == Some(key.as_str()))
+		.collect();
+
+	serde_json::json!({
+		"apiVersion": "batch/v1",
+		"kind": "CronJob",
+		"metadata": {
+			"name": job.metadata.name,
+			"namespace": job.metadata.namespace,
+		},
+		"spec": {
+			"jobTemplate": {
+				"spec": {
+					"template": {
+						"metadata": {
+							"annotations": annotations,
+						},
+						"spec": {
+							"containers": containers,
+						},
+					},
+				},
+			},
+		},
+	})
+}
+
+/// Where the pod template's metadata lives in a CronJob.
+const POD_METADATA_PATH: &str = "/spec/jobTemplate/spec/template/metadata";
+
+/// Sets the pod template's `key` annotation to `value`, so the pod can read it through the downward API.
+///
+/// The annotations (and the metadata holding them) are created when the template has none.
+/// Like [inject_env], the change is recorded as a JSON Patch operation.
+fn annotate_pod(job: &mut CronJob, key: &str, value: &str, ops: &mut Vec<PatchOperation>) {
+	let Some(template) = job.spec.as_mut().and_then(|spec| spec.job_template.spec.as_mut()).map(|spec| &mut spec.template) else {
+		return;
+	};
+	let annotation = BTreeMap::from([(key.to_string(), value.to_string())]);
+
+	let Some(metadata) = &mut template.metadata else {
+		ops.push(PatchOperation::Add(AddOperation {
+			path: POD_METADATA_PATH.into(),
+			value: serde_json::json!({ "annotations": annotation }),
+		}));
+		template.metadata = Some(ObjectMeta {
+			annotations: Some(annotation),
+			..Default::default()
+		});
+		return;
+	};
+
+	match &mut metadata.annotations {
+		None => {
+			ops.push(PatchOperation::Add(AddOperation {
+				path: format!("{}/annotations", POD_METADATA_PATH),
+				value: serde_json::json!(annotation),
+			}));
+			metadata.annotations = Some(annotation);
+		}
+		Some(annotations) if annotations.get(key).map(String::as_str) == Some(value) => {}
+		Some(annotations) => {
+			// An "add" replaces the member when it's already there.
+			ops.push(PatchOperation::Add(AddOperation {
+				path: format!("{}/annotations/{}", POD_METADATA_PATH, key.replace('~', "~0").replace('/', "~1")),
+				value: serde_json::json!(value),
+			}));
+			annotations.insert(key.into(), value.into());
+		}
+	}
+}
+
+fn env_path(container: usize) -> String {
+	format!("/spec/jobTemplate/spec/template/spec/containers/{}/env", container)
+}
+
+/// Which existing --env-key variables are replaced.
+#[derive(Debug, Clone, Copy)]
+enum Overwrite {
+	/// None, only missing variables are added.
+	Never,
+	/// Only those with a literal value, as `valueFrom` means they're managed elsewhere.
+	Values,
+	All,
+}
+
+/// Sets `key` to `value` on every container that defines an environment (or only those named in `only`).
+///
+/// A repeated container name is only injected once, into the first container with it.
+/// Every change is also recorded as a JSON Patch operation, against the state left by the previous ones.
+/// Returns the names of the containers whose variable is set through `valueFrom`, and so was left alone.
+fn inject_env<'a>(
+	containers: &'a mut [Container],
+	only: &[String],
+	key: &str,
+	value: &str,
+	overwrite: Overwrite,
+	ops: &mut Vec<PatchOperation>,
+) -> Vec<&'a str> {
+	let mut externally_managed = vec![];
+	let mut seen = HashSet::new();
+
+	for (index, container) in containers.iter_mut().enumerate() {
+		if !only.is_empty() && !only.contains(&container.name) {
+			continue;
+		}
+		if !seen.insert(container.name.clone()) {
+			continue;
+		}
+		let Some(env) = &mut container.env else {
+			continue;
+		};
+
+		let var = EnvVar {
+			name: key.into(),
+			value: Some(value.into()),
+			..Default::default()
+		};
+
+		match env.iter().position(|env| env.name == key) {
+			Some(position) => {
+				let item = &mut env[position];
+				match overwrite {
+					Overwrite::Never => continue,
+					Overwrite::Values if item.value_from.is_some() => {
+						externally_managed.push(&*container.name);
+						continue;
+					}
+					Overwrite::Values | Overwrite::All => {}
+				}
+				if *item != var {
+					*item = var;
+					ops.push(PatchOperation::Replace(ReplaceOperation {
+						path: format!("{}/{}", env_path(index), position),
+						value: serde_json::json!(item),
+					}));
+				}
+			}
+			None => {
+				ops.push(PatchOperation::Add(AddOperation {
+					path: format!("{}/-", env_path(index)),
+					value: serde_json::json!(var),
+				}));
+				env.push(var);
+			}
+		}
+	}
+
+	externally_managed
+}
+
+/// The container names that occur more than once, e.g. when the pod template was generated.
+fn duplicate_containers(containers: &[Container]) -> Vec<&str> {
+	let mut seen = HashSet::new();
+	let mut duplicates: Vec<_> = containers.iter()
+		.map(|container| &*container.name)
+		.filter(|name| !seen.insert(*name))
+		.collect();
+	duplicates.sort_unstable();
+	duplicates.dedup();
+	duplicates
+}
+
+/// Removes the env variables named in `keys` from every container.
+///
+/// Like [inject_env], every removal is recorded as a JSON Patch operation.
+fn remove_env(containers: &mut [Container], keys: &[String], ops: &mut Vec<PatchOperation>) {
+	for (index, container) in containers.iter_mut().enumerate() {
+		let Some(env) = &mut container.env else {
+			continue;
+		};
+
+		// Remove from the back, so the remaining positions stay valid.
+		for position in (0..env.len()).rev() {
+			if keys.contains(&env[position].name) {
+				env.remove(position);
+				ops.push(PatchOperation::Remove(RemoveOperation {
+					path: format!("{}/{}", env_path(index), position),
+				}));
+			}
+		}
+	}
+}
+
+/// The env variables named in `keys` that are present in any container.
+fn stale_env<'a>(containers: &'a [Container], keys: &[String]) -> Vec<&'a str> {
+	let mut stale: Vec<_> = containers.iter()
+		.filter_map(|container| container.env.as_ref())
+		.flatten()
+		.filter(|var| keys.contains(&var.name))
+		.map(|var| &*var.name)
+		.collect();
+	stale.sort_unstable();
+	stale.dedup();
+	stale
+}
+
+#[cfg(test)]
+mod tests {
+	use k8s_openapi::api::batch::v1::{CronJobSpec, JobSpec, JobTemplateSpec};
+	use k8s_openapi::api::core::v1::{EnvVarSource, PodSpec, PodTemplateSpec};
+
+	use super::*;
+	use crate::testing::{args, check, healthchecks, namespace_plan, planned_job, Server};
+
+	fn container(name: &str, env: Option<Vec<EnvVar>>) -> Container {
+		Container {
+			name: name.into(),
+			env,
+			..Default::default()
+		}
+	}
+
+	fn var(name: &str, value: &str) -> EnvVar {
+		EnvVar {
+			name: name.into(),
+			value: Some(value.into()),
+			..Default::default()
+		}
+	}
+
+	fn job(metadata: Option<ObjectMeta>, containers: Vec<Container>) -> CronJob {
+		CronJob {
+			spec: Some(CronJobSpec {
+				job_template: JobTemplateSpec {
+					spec: Some(JobSpec {
+						template: PodTemplateSpec {
+							metadata,
+							spec: Some(PodSpec {
+								containers,
+								..Default::default()
+							}),
+						},
+						..Default::default()
+					}),
+					..Default::default()
+				},
+				..Default::default()
+			}),
+			..Default::default()
+		}
+	}
+
+	fn patch(ops: Vec<PatchOperation>) -> serde_json::Value {
+		serde_json::to_value(json_patch::Patch(ops)).unwrap()
+	}
+
+	/// Applies the plan against the stand-in HealthChecks, returning the summary and the calls that were made.
+	async fn applied(healthchecks: &Server, flags: &[&str], plan: NamespacePlan) -> (serde_json::Value, Vec<String>) {
+		let args = args(&[&["--hc-key", "key", "--hc-url", healthchecks.url.as_str()], flags, &["prod"]].concat());
+		let projects = Projects::new(&args).unwrap();
+		let mut summary = Summary::new(false);
+		apply(&args, &projects, vec![plan], false, &mut summary).await.unwrap();
+
+		let calls = healthchecks.requests()
+			.into_iter()
+			.map(|request| format!("{} {}", request.method, request.path))
+			.collect();
+		(serde_json::to_value(&summary).unwrap(), calls)
+	}
+
+	/// The [planned_job] `name`, whose check is unique by name (as it is by default).
+	fn unique_job(name: &str) -> PlannedJob {
+		let mut planned = planned_job(name);
+		planned.check.unique = Some(vec!["name".into()]);
+		planned
+	}
+
+	/// A stand-in Kubernetes API, that answers every request with the CronJob "nightly-backup-job".
+	fn cron_jobs() -> Server {
+		Server::start(|_| (200, serde_json::to_value(crate::testing::cron_job("nightly-backup-job", "0 2 * * *")).unwrap()))
+	}
+
+	/// A plan for the job "nightly-backup-job" (whose container has an empty env), which writes to the `cluster`.
+	fn writing_plan(cluster: &Server) -> NamespacePlan {
+		let mut planned = planned_job("nightly-backup-job");
+		let (_, _, containers) = crate::plan::describe(&mut planned.job).unwrap();
+		containers[0].env = Some(vec![]);
+		NamespacePlan {
+			api: Some(kube::Api::namespaced(cluster.kube_client(), "billing")),
+			jobs: vec![planned],
+			..namespace_plan()
+		}
+	}
+
+	#[test]
+	fn notes_a_namespace_without_cron_jobs() {
+		let plan = namespace_plan();
+		assert_eq!(empty_notice(&plan).as_deref(), Some("No matching CronJobs (the namespace has none)"));
+	}
+
+	#[test]
+	fn notes_a_namespace_whose_cron_jobs_were_all_filtered_out() {
+		let plan = NamespacePlan {
+			listed: 3,
+			..namespace_plan()
+		};
+		assert_eq!(empty_notice(&plan).as_deref(), Some("No matching CronJobs (all 3 were filtered out)"));
+	}
+
+	#[test]
+	fn notes_nothing_when_a_job_was_skipped() {
+		let plan = NamespacePlan {
+			listed: 1,
+			skipped: vec![("nightly-report-job".into(), SkipReason::NoJobTemplate)],
+			..namespace_plan()
+		};
+		assert_eq!(empty_notice(&plan), None);
+	}
+
+	#[test]
+	fn fills_in_the_env_value_template() {
+		let ping_url = "https://hc.example.com/ping/f6a2";
+		assert_eq!(env_value("{id}", "f6a2", ping_url), "f6a2");
+		assert_eq!(env_value("{ping_url}", "f6a2", ping_url), ping_url);
+		assert_eq!(env_value("id={id} url={ping_url} again={id}", "f6a2", ping_url), "id=f6a2 url=https://hc.example.com/ping/f6a2 again=f6a2");
+	}
+
+	#[test]
+	fn remove_env_removes_from_the_back() {
+		let mut containers = vec![
+			container("app", Some(vec![var("A", "1"), var("KEEP", "2"), var("B", "3")])),
+			container("no-env", None),
+		];
+		let mut ops = vec![];
+		remove_env(&mut containers, &["A".into(), "B".into()], &mut ops);
+
+		assert_eq!(patch(ops), serde_json::json!([
+			{ "op": "remove", "path": "/spec/jobTemplate/spec/template/spec/containers/0/env/2" },
+			{ "op": "remove", "path": "/spec/jobTemplate/spec/template/spec/containers/0/env/0" },
+		]));
+		assert_eq!(containers[0].env.as_deref(), Some(&[var("KEEP", "2")][..]));
+	}
+
+	#[test]
+	fn inject_env_adds_and_replaces() {
+		let mut containers = vec![
+			container("app", Some(vec![var("OTHER", "1")])),
+			container("sidecar", Some(vec![var("HC_ID", "old")])),
+			container("no-env", None),
+		];
+		let mut ops = vec![];
+		let managed = inject_env(&mut containers, &[], "HC_ID", "abc", Overwrite::Values, &mut ops);
+
+		assert!(managed.is_empty());
+		assert_eq!(patch(ops), serde_json::json!([
+			{ "op": "add", "path": "/spec/jobTemplate/spec/template/spec/containers/0/env/-", "value": { "name": "HC_ID", "value": "abc" } },
+			{ "op": "replace", "path": "/spec/jobTemplate/spec/template/spec/containers/1/env/0", "value": { "name": "HC_ID", "value": "abc" } },
+		]));
+		assert_eq!(containers[0].env.as_ref().unwrap()[1], var("HC_ID", "abc"));
+		assert!(containers[2].env.is_none());
+	}
+
+	#[tokio::test]
+	async fn a_renamed_job_keeps_its_check_by_uid() {
+		let healthchecks = healthchecks(vec![check("f6a2", "old-backup-job", "uid:5f1c")]);
+		let mut planned = planned_job("nightly-backup-job");
+		planned.job.metadata.uid = Some("5f1c".into());
+		planned.check.tags = Some("uid:5f1c".into());
+		let plan = NamespacePlan {
+			jobs: vec![planned],
+			..namespace_plan()
+		};
+
+		let (summary, calls) = applied(&healthchecks, &["--key-by-uid"], plan).await;
+		assert_eq!(calls, ["GET /checks", "POST /checks/f6a2"]);
+		assert_eq!(summary["outcomes"], serde_json::json!([
+			{ "context": "prod", "namespace": "billing", "name": "nightly-backup-job", "check": "f6a2", "action": "updated" },
+		]));
+	}
+
+	#[tokio::test]
+	async fn jobs_with_the_same_external_id_share_a_check() {
+		let healthchecks = healthchecks(vec![]);
+		let jobs = ["nightly-backup-job", "nightly-backup-replica-job"].into_iter()
+			.map(|name| {
+				let mut planned = planned_job(name);
+				planned.job.metadata.annotations = Some([("example.com/check".to_string(), "backup".to_string())].into());
+				planned.check.tags = Some("extid:backup".into());
+				planned
+			})
+			.collect();
+		let plan = NamespacePlan {
+			jobs,
+			..namespace_plan()
+		};
+
+		let (summary, calls) = applied(&healthchecks, &["--external-id-annotation", "example.com/check"], plan).await;
+		assert_eq!(calls, ["GET /checks", "POST /checks/", "POST /checks/new-0"]);
+		assert_eq!(summary["created"], 1);
+		assert_eq!(summary["updated"], 1);
+	}
+
+	#[test]
+	fn inject_env_leaves_value_from_alone_unless_asked_to() {
+		let from = EnvVar {
+			name: "HC_ID".into(),
+			value_from: Some(EnvVarSource::default()),
+			..Default::default()
+		};
+		let mut containers = vec![container("app", Some(vec![from]))];
+
+		let mut ops = vec![];
+		let managed = inject_env(&mut containers, &[], "HC_ID", "abc", Overwrite::Values, &mut ops);
+		assert_eq!(managed, ["app"]);
+		assert!(ops.is_empty());
+
+		let mut ops = vec![];
+		let managed = inject_env(&mut containers, &[], "HC_ID", "abc", Overwrite::All, &mut ops);
+		assert!(managed.is_empty());
+		assert_eq!(patch(ops), serde_json::json!([
+			{ "op": "replace", "path": "/spec/jobTemplate/spec/template/spec/containers/0/env/0", "value": { "name": "HC_ID", "value": "abc" } },
+		]));
+	}
+
+	#[test]
+	fn inject_env_only_adds_a_missing_variable_when_asked_to() {
+		let mut containers = vec![
+			container("app", Some(vec![var("HC_ID", "old")])),
+			container("other", Some(vec![])),
+		];
+		let mut ops = vec![];
+		let managed = inject_env(&mut containers, &[], "HC_ID", "abc", Overwrite::Never, &mut ops);
+
+		assert!(managed.is_empty());
+		assert_eq!(patch(ops), serde_json::json!([
+			{ "op": "add", "path": "/spec/jobTemplate/spec/template/spec/containers/1/env/-", "value": { "name": "HC_ID", "value": "abc" } },
+		]));
+		assert_eq!(containers[0].env.as_deref(), Some(&[var("HC_ID", "old")][..]));
+	}
+
+	#[test]
+	fn inject_env_only_touches_the_named_containers() {
+		let mut containers = vec![
+			container("app", Some(vec![])),
+			container("job", Some(vec![var("HC_ID", "old")])),
+			container("other", Some(vec![])),
+		];
+		let mut ops = vec![];
+		inject_env(&mut containers, &["job".into()], "HC_ID", "abc", Overwrite::Values, &mut ops);
+
+		assert_eq!(patch(ops), serde_json::json!([
+			{ "op": "replace", "path": "/spec/jobTemplate/spec/template/spec/containers/1/env/0", "value": { "name": "HC_ID", "value": "abc" } },
+		]));
+	}
+	#[tokio::test]
+	async fn each_action_comes_from_its_own_path() {
+		let mut unchanged = check("a1", "kept-job", "");
+		unchanged.schedule = Some("0 2 * * *".into());
+		let healthchecks = healthchecks(vec![unchanged, check("b2", "changed-job", "")]);
+		let plan = NamespacePlan {
+			jobs: vec![unique_job("kept-job"), unique_job("changed-job"), unique_job("new-job")],
+			skipped: vec![("broken-job".into(), SkipReason::InvalidSchedule)],
+			..namespace_plan()
+		};
+		let (summary, _) = applied(&healthchecks, &[], plan).await;
+		let actions: Vec<_> = summary["outcomes"].as_array().unwrap().iter()
+			.map(|outcome| (outcome["name"].as_str().unwrap(), outcome["action"].as_str().unwrap()))
+			.collect();
+		assert_eq!(actions, [
+			("broken-job", "skipped"),
+			("kept-job", "unchanged"),
+			("changed-job", "updated"),
+			("new-job", "created"),
+		]);
+
+		let failing = Server::start(|request| match request.method.as_str() {
+			"GET" => (200, serde_json::json!({ "checks": [] })),
+			_ => (500, serde_json::json!({ "error": "unavailable" })),
+		});
+		let plan = NamespacePlan {
+			jobs: vec![planned_job("new-job")],
+			..namespace_plan()
+		};
+		let (summary, _) = applied(&failing, &[], plan).await;
+		assert_eq!(summary["outcomes"][0]["action"], "failed");
+		assert_eq!(summary["failed"], 1);
+	}
+
+	#[tokio::test]
+	async fn a_dry_run_summary_counts_what_would_happen() {
+		let healthchecks = healthchecks(vec![check("b2", "changed-job", "")]);
+		let plan = NamespacePlan {
+			jobs: vec![planned_job("changed-job"), planned_job("new-job")],
+			..namespace_plan()
+		};
+		let (summary, calls) = applied(&healthchecks, &["--dry-run", "--summary-only"], plan).await;
+
+		assert_eq!(calls, ["GET /checks"]);
+		assert_eq!(summary["created"], 1);
+		assert_eq!(summary["updated"], 1);
+		assert_eq!(summary["outcomes"].as_array().unwrap().len(), 2);
+	}
+
+	#[test]
+	fn the_start_url_follows_the_ping_url() {
+		assert_eq!(start_url("https://hc.example.com/ping/f6a2"), "https://hc.example.com/ping/f6a2/start");
+		assert_eq!(start_url("https://hc.example.com/ping/f6a2/"), "https://hc.example.com/ping/f6a2/start");
+	}
+
+	#[tokio::test]
+	async fn injects_the_start_url() {
+		let cluster = cron_jobs();
+		let healthchecks = healthchecks(vec![]);
+		applied(&healthchecks, &["--env-key", "HC_ID", "--start-url-env", "HC_START_URL"], writing_plan(&cluster)).await;
+
+		let requests = cluster.requests();
+		assert_eq!(requests.len(), 1);
+		let patch: serde_json::Value = serde_json::from_str(&requests[0].body).unwrap();
+		assert!(patch.as_array().unwrap().iter().any(|op| op["value"] == serde_json::json!({ "name": "HC_START_URL", "value": "https://hc.example.com/ping/new-0/start" })), "{}", patch);
+	}
+
+	#[tokio::test]
+	async fn a_matching_spec_hash_skips_the_upsert() {
+		let mut plans = vec![NamespacePlan {
+			jobs: vec![planned_job("nightly-backup-job")],
+			..namespace_plan()
+		}];
+		crate::plan::add_spec_hashes(&mut plans);
+		let tags = plans[0].jobs[0].check.tags.clone().unwrap();
+		assert!(tags.starts_with("hkspec:"), "{}", tags);
+
+		// Unlike the planned check, it has no schedule, so only the hash tells that it's unchanged.
+		let healthchecks = healthchecks(vec![check("a1", "nightly-backup-job", &tags)]);
+		let (summary, calls) = applied(&healthchecks, &["--spec-hash"], plans.remove(0)).await;
+
+		assert_eq!(calls, ["GET /checks"]);
+		assert_eq!(summary["unchanged"], 1);
+	}
+
+	#[test]
+	fn env_only_keeps_only_the_keys() {
+		let job = job(None, vec![
+			container("app", Some(vec![var("OTHER", "1"), var("HC_ID", "abc")])),
+			container("sidecar", Some(vec![var("OTHER", "2")])),
+		]);
+		let applied = env_only(&job, &["HC_ID"], None);
+		assert_eq!(
+			applied["spec"]["jobTemplate"]["spec"]["template"]["spec"]["containers"],
+			serde_json::json!([{ "name": "app", "env": [{ "name": "HC_ID", "value": "abc" }] }]),
+		);
+	}
+
+	#[tokio::test]
+	async fn writes_the_job_by_the_replace_strategy() {
+		let write = |strategy: &'static str| async move {
+			let cluster = cron_jobs();
+			let flags = ["--env-key", "HC_ID", "--replace-strategy", strategy, "--field-manager", "gitops-friendly"];
+			applied(&healthchecks(vec![]), &flags, writing_plan(&cluster)).await;
+
+			let requests = cluster.requests();
+			assert_eq!(requests.len(), 1);
+			let request = &requests[0];
+			let (path, query) = request.path.split_once('?').unwrap_or((&request.path, ""));
+			let mut params: Vec<_> = query.split('&').filter(|param| !param.is_empty()).collect();
+			params.sort_unstable();
+			let content_type = request.header("content-type").unwrap_or_default();
+			format!("{} {} {} [{}]", request.method, path, content_type, params.join(", "))
+		};
+
+		let path = "/apis/batch/v1/namespaces/billing/cronjobs/nightly-backup-job";
+		assert_eq!(write("replace").await, format!("PUT {} application/json []", path));
+		assert_eq!(write("patch").await, format!("PATCH {} application/json-patch+json []", path));
+		assert_eq!(write("apply").await, format!("PATCH {} application/apply-patch+yaml [fieldManager=gitops-friendly, force=true]", path));
+	}
+
+	#[tokio::test]
+	async fn stops_creating_at_the_check_limit_but_still_updates() {
+		// Like a project at its limit: an upsert only goes through when it updates the existing check.
+		let healthchecks = Server::start(|request| {
+			let existing = check("a1", "existing-job", "");
+			match request.method.as_str() {
+				"GET" => (200, serde_json::json!({ "checks": [existing] })),
+				_ if request.body.contains("\"existing-job\"") => (200, serde_json::to_value(existing).unwrap()),
+				_ => (403, serde_json::json!({ "error": "check limit reached" })),
+			}
+		});
+		let plan = NamespacePlan {
+			jobs: vec![unique_job("first-new-job"), unique_job("second-new-job"), unique_job("existing-job")],
+			..namespace_plan()
+		};
+		let (summary, calls) = applied(&healthchecks, &[], plan).await;
+
+		assert_eq!(calls, ["GET /checks", "POST /checks/", "POST /checks/"]);
+		let actions: Vec<_> = summary["outcomes"].as_array().unwrap().iter()
+			.map(|outcome| format!("{} {} {}", outcome["name"], outcome["action"], outcome["reason"]))
+			.collect();
+		assert_eq!(actions, [
+			r#""first-new-job" "failed" null"#,
+			r#""second-new-job" "skipped" "check limit reached""#,
+			r#""existing-job" "updated" null"#,
+		]);
+	}
+
+	#[test]
+	fn inject_env_leaves_unchanged_and_duplicate_containers_alone() {
+		let mut containers = vec![
+			container("app", Some(vec![var("HC_ID", "abc")])),
+			container("app", Some(vec![])),
+		];
+		assert_eq!(duplicate_containers(&containers), ["app"]);
+
+		let mut ops = vec![];
+		inject_env(&mut containers, &[], "HC_ID", "abc", Overwrite::All, &mut ops);
+		assert!(ops.is_empty());
+	}
+
+	#[test]
+	fn shows_the_check_that_would_be_sent() {
+		let check = NewCheck {
+			name: Some("nightly-backup-job".into()),
+			schedule: Some("0 2 * * *".into()),
+			tz: Some("UTC".into()),
+			timeout: Some(3600),
+			grace: Some(900),
+			tags: Some("nightly backup".into()),
+			channels: Some("".into()),
+			unique: Some(vec!["name".into()]),
+			..Default::default()
+		};
+		let lines = check_lines(&check);
+		assert!(lines.len() > 1);
+
+		let sent: serde_json::Value = serde_json::from_str(&lines.join("\n")).unwrap();
+		for (field, value) in [
+			("name", serde_json::json!("nightly-backup-job")),
+			("schedule", serde_json::json!("0 2 * * *")),
+			("tz", serde_json::json!("UTC")),
+			("timeout", serde_json::json!(3600)),
+			("grace", serde_json::json!(900)),
+			("tags", serde_json::json!("nightly backup")),
+			("channels", serde_json::json!("")),
+			("unique", serde_json::json!(["name"])),
+		] {
+			assert_eq!(sent[field], value, "{}", field);
+		}
+	}
+
+	#[tokio::test]
+	async fn verifies_the_touched_checks() {
+		let healthchecks = healthchecks(vec![]);
+		let mut planned = planned_job("nightly-backup-job");
+		// The stand-in always keeps a grace of an hour.
+		planned.check.grace = Some(900);
+		let plan = NamespacePlan {
+			jobs: vec![planned],
+			..namespace_plan()
+		};
+		let warnings = crate::output::warnings();
+		let (_, calls) = applied(&healthchecks, &["--verify"], plan).await;
+
+		assert_eq!(calls, ["GET /checks", "POST /checks/", "GET /checks/new-0"]);
+		assert!(crate::output::warnings() > warnings);
+	}
+
+	#[test]
+	fn append_channels_merges_without_duplicates() {
+		assert_eq!(append_channels(Some("b, a".into()), Some("a,c,")), Some("a,b,c".into()));
+		assert_eq!(append_channels(Some("a".into()), None), Some("a".into()));
+		assert_eq!(append_channels(None, Some("a")), None);
+	}
+
+	#[tokio::test]
+	async fn keeps_the_existing_channels_when_appending() {
+		let mut existing = check("a1", "nightly-backup-job", "");
+		existing.channels = Some("3333".into());
+		let healthchecks = healthchecks(vec![existing]);
+		let mut planned = unique_job("nightly-backup-job");
+		planned.check.channels = Some("1111".into());
+		let plan = NamespacePlan {
+			jobs: vec![planned],
+			..namespace_plan()
+		};
+		applied(&healthchecks, &["--append-channels"], plan).await;
+
+		let upsert = healthchecks.requests().into_iter().find(|request| request.method == "POST").unwrap();
+		let sent: serde_json::Value = serde_json::from_str(&upsert.body).unwrap();
+		assert_eq!(sent["channels"], "1111,3333");
+	}
+
+	#[tokio::test]
+	async fn pauses_only_the_checks_of_the_targets() {
+		let healthchecks = healthchecks(vec![check("a1", "nightly-backup-job", ""), check("b2", "hand-made-check", "")]);
+		let args = args(&["--hc-key", "key", "--hc-url", &healthchecks.url, "pause", "prod"]);
+		let plan = NamespacePlan {
+			jobs: vec![planned_job("nightly-backup-job"), planned_job("job-without-a-check")],
+			..namespace_plan()
+		};
+		let mut summary = Summary::new(false);
+		pause(&args, &Projects::new(&args).unwrap(), vec![plan], false, &mut summary).await.unwrap();
+
+		let calls: Vec<_> = healthchecks.requests()
+			.into_iter()
+			.map(|request| format!("{} {}", request.method, request.path))
+			.collect();
+		assert_eq!(calls, ["GET /checks", "POST /checks/a1/pause"]);
+		assert_eq!(serde_json::to_value(&summary).unwrap()["paused"], 1);
+	}
+
+	#[tokio::test]
+	async fn pauses_the_checks_of_a_terminating_namespace() {
+		let healthchecks = healthchecks(vec![check("a1", "nightly-backup-job", "")]);
+		let plan = NamespacePlan {
+			terminating: true,
+			jobs: vec![planned_job("nightly-backup-job")],
+			..namespace_plan()
+		};
+		let (summary, calls) = applied(&healthchecks, &[], plan).await;
+
+		assert_eq!(calls, ["GET /checks", "POST /checks/a1/pause"]);
+		assert_eq!((summary["paused"].as_u64(), summary["updated"].as_u64()), (Some(1), Some(0)));
+	}
+
+	#[test]
+	fn annotate_pod_creates_what_is_missing() {
+		let mut ops = vec![];
+		annotate_pod(&mut job(None, vec![]), "example.com/check", "abc", &mut ops);
+		assert_eq!(patch(ops), serde_json::json!([
+			{ "op": "add", "path": POD_METADATA_PATH, "value": { "annotations": { "example.com/check": "abc" } } },
+		]));
+
+		let mut ops = vec![];
+		annotate_pod(&mut job(Some(ObjectMeta::default()), vec![]), "example.com/check", "abc", &mut ops);
+		assert_eq!(patch(ops), serde_json::json!([
+			{ "op": "add", "path": format!("{}/annotations", POD_METADATA_PATH), "value": { "example.com/check": "abc" } },
+		]));
+	}
+
+	#[test]
+	fn annotate_pod_escapes_the_key() {
+		let metadata = ObjectMeta {
+			annotations: Some(BTreeMap::from([("example.com/check".to_string(), "old".to_string())])),
+			..Default::default()
+		};
+		let mut job = job(Some(metadata), vec![]);
+
+		let mut ops = vec![];
+		annotate_pod(&mut job, "example.com/check", "abc", &mut ops);
+		assert_eq!(patch(ops), serde_json::json!([
+			{ "op": "add", "path": format!("{}/annotations/example.com~1check", POD_METADATA_PATH), "value": "abc" },
+		]));
+
+		let mut ops = vec![];
+		annotate_pod(&mut job, "example.com/check", "abc", &mut ops);
+		assert!(ops.is_empty());
+	}
+}
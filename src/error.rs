@@ -0,0 +1,95 @@
+use kube::config::KubeconfigError;
+
+/// Errors raised while planning or applying a synchronisation.
+#[derive(Debug, thiserror::Error)]
+pub enum SyncError {
+	#[error("Unable to read the kubeconfig")]
+	Kubeconfig(#[source] KubeconfigError),
+
+	#[error("Unable to read the fixture {}", path.display())]
+	Fixture {
+		path: std::path::PathBuf,
+		#[source]
+		source: std::io::Error,
+	},
+
+	#[error("Unable to parse the fixture {}", path.display())]
+	FixtureFormat {
+		path: std::path::PathBuf,
+		#[source]
+		source: serde_json::Error,
+	},
+
+	#[error("Invalid target \"{target}\"")]
+	Target {
+		target: String,
+		#[source]
+		source: crate::target::TargetError,
+	},
+
+	#[error("Invalid pattern in \"{target}\"")]
+	Pattern {
+		target: String,
+		#[source]
+		source: regex::Error,
+	},
+
+	#[error("Unknown kubernetes context(s): {} (available: {})", missing.join(", "), available.join(", "))]
+	UnknownContexts {
+		missing: Vec<String>,
+		available: Vec<String>,
+	},
+
+	#[error("Unable to load the kubernetes context \"{context}\"")]
+	Context {
+		context: String,
+		#[source]
+		source: KubeconfigError,
+	},
+
+	#[error("Unable to create a kubernetes client for \"{context}\"")]
+	Client {
+		context: String,
+		#[source]
+		source: kube::Error,
+	},
+
+	#[error("Unable to list the namespaces in \"{context}\"")]
+	Namespaces {
+		context: String,
+		#[source]
+		source: kube::Error,
+	},
+
+	#[error("Unable to list the CronJobs in \"{context}:{namespace}\"")]
+	List {
+		context: String,
+		namespace: String,
+		#[source]
+		source: kube::Error,
+	},
+
+	#[error("The CronJob \"{job}\" has an invalid {} annotation: {reason}", crate::plan::SCHEDULE_ANNOTATION)]
+	InvalidSchedule {
+		job: String,
+		reason: crate::schedule::ScheduleError,
+	},
+
+	#[error("Unable to list the existing checks")]
+	Checks(#[source] healthchecks::errors::HealthchecksApiError),
+
+	#[error("The CronJob \"{job}\" in \"{namespace}\" has no container named {}", containers.join(", "))]
+	MissingContainers {
+		job: String,
+		namespace: String,
+		containers: Vec<String>,
+	},
+
+	#[error("Unable to update the CronJob \"{job}\" in \"{namespace}\"")]
+	Patch {
+		job: String,
+		namespace: String,
+		#[source]
+		source: kube::Error,
+	},
+}
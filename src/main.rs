@@ -1,22 +1,43 @@
-#![feature(let_else)]
-#![feature(iter_intersperse)]
-#![feature(vec_retain_mut)]
-
 use std::collections::HashMap;
+use std::io::{BufRead, IsTerminal};
+use std::path::PathBuf;
+use std::time::Duration;
 
 use anyhow::{Context as _, Result};
-use clap::{Args as ClapArgs, Parser};
-use healthchecks::model::NewCheck;
-use k8s_openapi::api::batch::v1::CronJob;
-use k8s_openapi::api::core::v1::Container;
-use k8s_openapi::api::core::v1::EnvVar;
-use kube::{Client, Config, ResourceExt};
-use kube::api::{ListParams, PostParams};
-use kube::config::{Kubeconfig, KubeConfigOptions};
-
-#[derive(Parser, Debug)]
-#[clap(name = "healthkube", version, author = "Jezza")]
+use clap::{ArgEnum, Args as ClapArgs, CommandFactory, Parser, Subcommand};
+use clap_complete::Shell;
+use healthchecks::manage::ManageClient;
+use healthchecks::model::{Channel, NewCheck};
+use k8s_openapi::api::core::v1::Secret;
+use regex::Regex;
+
+mod apply;
+mod error;
+mod output;
+mod plan;
+mod redact;
+mod report;
+mod schedule;
+mod style;
+mod summary;
+mod target;
+#[cfg(test)]
+mod testing;
+mod trace;
+mod validate;
+
+use output::{say, warning};
+use style::{ColorChoice, Status};
+use summary::{Action, Outcome, Summary};
+
+#[derive(Parser, Debug, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+#[clap(name = "healthkube", version, author = "Jezza", subcommand_negates_reqs = true)]
 struct Args {
+	#[clap(subcommand)]
+	#[serde(skip)]
+	command: Option<Command>,
+
 	#[clap(flatten)]
 	hc: HealthChecksInfo,
 
@@ -25,43 +46,510 @@ struct Args {
 	#[clap(long)]
 	dry_run: bool,
 
+	/// Asks before every check that's created, updated or deleted, and leaves it alone unless answered with "y".
+	/// Note: It needs an interactive terminal.
+	#[clap(long, conflicts_with = "dry-run")]
+	confirm_each: bool,
+
+	/// Answers "y" to every question, like --confirm-each's or the one before --clear-existing-checks deletes anything.
+	/// Needed when there's no terminal to ask on.
+	#[clap(long, visible_alias = "yes")]
+	assume_yes: bool,
+
+	/// Prints the settings, as resolved from the flags, env vars and defaults, as JSON and exits.
+	/// Note: The secrets are redacted.
+	#[clap(long)]
+	#[serde(skip)]
+	dump_effective_config: bool,
+
+	/// Fails the run (after it's done everything else) if any warning was printed, e.g. for gating CI on a clean state.
+	/// Note: Every "Warning:" counts, like unknown integrations (under --allow-unknown-integrations), invalid or empty schedules,
+	/// name collisions under "--on-name-collision warn", empty namespaces under --warn-empty, and missing containers under --allow-missing-env-containers.
+	#[clap(long)]
+	strict: bool,
+
+	/// Exits with code 2 if the dry-run would create, update or delete any check, and 0 otherwise.
+	#[clap(long, requires = "dry-run")]
+	detect_changes: bool,
+
+	/// Prints more details about what's happening.
+	/// Note: In combination with --dry-run, it'll print how often each segment occurs to help tune --rank.
+	/// Note: It'll also print every check that a dry-run would send, as JSON.
+	/// Note: It'll also print how long each kind of API call took, at the end of the run.
+	/// Note: At -vvv, every HTTP call to Kubernetes and HealthChecks is logged (without any bodies).
+	#[clap(short, long, parse(from_occurrences))]
+	verbose: u8,
+
+	/// Copies the text output to this file as well (without any colors), e.g. for an audit trail.
+	/// Note: JSON output isn't copied, use --plan-out for the plan.
+	#[clap(long, value_name = "PATH")]
+	output_file: Option<PathBuf>,
+
+	/// The format of the output.
+	/// Note: With --dry-run, "json" prints the plan, otherwise the outcome of every job.
+	/// Note: "ndjson" prints every outcome on its own line as soon as it happens (and can't be used with --dry-run).
+	#[clap(long, arg_enum, default_value_t = OutputFormat::Text)]
+	output: OutputFormat,
+
+	/// Indents the "json" output (and the --plan-out file) for reading, instead of printing it on a single line.
+	/// Note: The document is the same either way, and "ndjson" always has a line per outcome.
+	#[clap(long)]
+	json_pretty: bool,
+
+	/// Writes the dry-run plan as JSON to this file.
+	#[clap(long, value_name = "PATH", requires = "dry-run")]
+	plan_out: Option<PathBuf>,
+
+	/// Only prints what changed since the dry-run plan in this file (e.g. one written by --plan-out): the new creates and updates, and those that were resolved.
+	/// Note: Pass it the same path as --plan-out, to compare every dry-run with the one before it.
+	/// Note: Without the file, everything that would change is new.
+	#[clap(long, value_name = "PATH", requires = "dry-run")]
+	plan_since: Option<PathBuf>,
+
+	/// When to colour the output.
+	/// Note: "auto" only colours it when stdout is a terminal, and NO_COLOR isn't set.
+	#[clap(long, arg_enum, value_name = "WHEN", default_value = "auto")]
+	color: ColorChoice,
+
+	/// Disables coloured output, same as --color never.
+	#[clap(long, hide = true)]
+	no_color: bool,
+
 	/// The frequency at which a segment will be considered common enough to be used as a tag.
 	#[clap(long, default_value_t = 3)]
 	rank: u8,
 
+	/// Only these segments may become tags (still subject to --rank).
+	#[clap(long = "tag-allowlist", value_name = "WORD")]
+	tag_allowlist: Vec<String>,
+
+	/// These segments will never become tags.
+	#[clap(long = "tag-denylist", value_name = "WORD", conflicts_with = "tag-allowlist")]
+	tag_denylist: Vec<String>,
+
+	/// Uses the timezone from the CRON_TZ (or TZ) variable of a job's first (or --primary-container) container, instead of --timezone.
+	/// Note: Unknown timezones are reported, and fall back to --timezone.
+	#[clap(long)]
+	tz_from_container_env: bool,
+
+	/// Caps how many of a job's name segments become tags, keeping the most frequent ones.
+	/// Note: The --tag-from-image, --external-id-annotation and --key-by-uid tags are added on top.
+	#[clap(long, value_name = "N")]
+	max_tags: Option<usize>,
+
+	/// Only synchronise the CronJobs matching this label selector, e.g. "team=billing,tier!=dev".
+	/// Note: The filtering is done by the API server.
+	#[clap(long, value_name = "SELECTOR", forbid_empty_values = true)]
+	label_selector: Option<String>,
+
+	/// Only synchronise the CronJobs matching this field selector, e.g. "metadata.name!=cleanup-job".
+	/// Note: The API server only supports "metadata.name" and "metadata.namespace" for CronJobs.
+	#[clap(long, value_name = "SELECTOR", forbid_empty_values = true)]
+	field_selector: Option<String>,
+
+	/// Only expands a "context:*" (or any other pattern) target to the namespaces matching this label selector, e.g. "healthkube=enabled".
+	/// Note: Namespaces that are named explicitly are used regardless.
+	#[clap(long, value_name = "SELECTOR", forbid_empty_values = true)]
+	namespace_selector: Option<String>,
+
+	/// Skips this namespace when expanding a "context:*" (or any other pattern) target.
+	#[clap(long = "exclude-namespace", value_name = "NAMESPACE")]
+	exclude_namespaces: Vec<String>,
+
+	/// Skips the namespaces matching this regex when expanding a "context:*" (or any other pattern) target.
+	#[clap(long, value_name = "REGEX")]
+	#[serde(serialize_with = "serialize_regex")]
+	exclude_namespace_regex: Option<Regex>,
+
 	/// The corresponding kubernetes jobs will be updated with an environment variable that uses
-	/// this argument as the key, and the HealthCheck id as the value.
+	/// this argument as the key, and the HealthCheck id as the value (see --env-value-template).
 	///
 	/// For example using, --env_key MY_HC_KEY, will cause all kubernetes jobs to be updated to contain an env variable like so: "MY_HC_KEY = {the healthcheck id}"
 	#[clap(long, env = "K8S_ENV_KEY")]
 	env_key: Option<String>,
 
+	/// The value that's injected under --env-key.
+	/// Supports the placeholders "{id}" and "{ping_url}", e.g. "https://hc.example.com/ping/{id}".
+	#[clap(long, default_value = "{id}", value_name = "TEMPLATE")]
+	env_value_template: String,
+
+	/// How the env changes are written back to the CronJobs.
+	/// Note: A "replace" can clobber concurrent changes, while an "apply" lets other field managers coexist.
+	#[clap(long, arg_enum, default_value = "patch")]
+	replace_strategy: ReplaceStrategy,
+
+	/// The field manager that owns the injected env, under "--replace-strategy apply".
+	/// Note: Other controllers (e.g. a GitOps one) then leave those fields alone, instead of fighting over them.
+	#[clap(long, default_value = "healthkube", value_name = "NAME", forbid_empty_values = true)]
+	field_manager: String,
+
+	/// Is the env key that'll be injected with the check's start ping url ("<ping_url>/start").
+	/// That way a job can ping it when it begins, and HealthChecks measures how long it ran.
+	/// Note: It's injected alongside --env-key, into the same containers.
+	#[clap(long, value_name = "KEY")]
+	start_url_env: Option<String>,
+
+	/// Also writes the check id into this annotation of the jobs' pod template, so a pod can read it through the downward API.
+	/// Note: It's written regardless of --env-key, and the pod template gets the annotations it's missing.
+	#[clap(long, value_name = "KEY")]
+	pod_annotation: Option<String>,
+
+	/// Only injects the --env-key variable into the container with this name, instead of all of them.
+	#[clap(long = "env-container", value_name = "NAME", requires = "env-key")]
+	env_containers: Vec<String>,
+
+	/// Only warn about jobs without a container named by --env-container, instead of refusing to continue.
+	#[clap(long = "allow-missing-env-containers")]
+	allow_missing_env_containers: bool,
+
+	/// Only adds the --env-key variable to containers that don't have it yet, leaving any existing value untouched.
+	#[clap(long, requires = "env-key", conflicts_with = "overwrite-value-from")]
+	env_key_if_missing_only: bool,
+
+	/// Replaces an existing --env-key variable that's set through valueFrom (e.g. a secret), instead of leaving it alone.
+	#[clap(long, requires = "env-key")]
+	overwrite_value_from: bool,
+
+	/// Removes this env variable from the jobs' containers, e.g. to clean up after renaming --env-key.
+	/// Note: Can't be used with "--replace-strategy apply".
+	#[clap(long = "remove-env", value_name = "KEY")]
+	remove_envs: Vec<String>,
+
+	/// Instead of synchronising, removes the --env-key variable from the jobs' containers.
+	/// Note: HealthChecks isn't contacted (nor needs configuring), this only backs the injection out of the jobs.
+	/// Note: Can't be used with "--replace-strategy apply".
+	#[clap(long, requires = "env-key")]
+	remove_managed_env: bool,
+
+	/// Aborts the synchronisation if it takes longer than this many seconds.
+	/// Note: A summary of what was done up until that point is printed, and the program exits with code 124.
+	#[clap(long, value_name = "SECS")]
+	run_timeout: Option<u64>,
+
+	/// Caps how many times a page of the namespace listing is retried across the whole run, after which a failure is reported straight away.
+	/// Note: Only the namespace listing is retried (at most twice per page), nothing else is.
+	#[clap(long, value_name = "N")]
+	retry_budget: Option<usize>,
+
+	/// Refuses to synchronise when more than this many checks would be created.
+	/// Note: A guard against misconfigured targets, use --force to go ahead anyway.
+	#[clap(long, value_name = "N")]
+	max_checks: Option<usize>,
+
+	/// Proceeds even when a safety limit, like --max-checks, is exceeded.
+	#[clap(long)]
+	force: bool,
+
+	/// Also tags each check with the image repository of the job's first (or --primary-container) container.
+	/// For example, "registry.example.com/team/billing:1.2" adds the tag "billing".
+	#[clap(long)]
+	tag_from_image: bool,
+
+	/// The container that --tag-from-image and --tz-from-container-env read from, instead of the first one (e.g. when that's a sidecar).
+	/// Note: Jobs without a container of that name fall back to their first one, with a warning.
+	#[clap(long, value_name = "NAME")]
+	primary_container: Option<String>,
+
+	/// Plans against the CronJobs in this file (e.g. "kubectl get cronjobs -A -o json"), instead of the clusters.
+	/// Nothing contacts Kubernetes or HealthChecks, so it can be used to lint the configuration.
+	#[clap(long, value_name = "FIXTURE", requires = "dry-run")]
+	offline: Option<PathBuf>,
+
+	/// Tags each check with its CronJob's uid ("uid:<uid>"), and updates the check carrying that tag.
+	/// That way a renamed CronJob keeps its check, instead of orphaning it.
+	/// Note: A job without a tagged check gets a new one, rather than taking over a check of the same name.
+	#[clap(long)]
+	key_by_uid: bool,
+
+	/// Tags each check with the id in this CronJob annotation ("extid:<id>"), and updates the check carrying that tag.
+	/// That way the check is identified by an id you control, no matter what the job (or check) is called.
+	/// Note: Jobs without the annotation are identified as usual, and an id with whitespace in it is ignored.
+	#[clap(long, value_name = "KEY")]
+	external_id_annotation: Option<String>,
+
+	/// Tags each check with a hash of its spec ("hkspec:<hash>"), and leaves a check alone while its hash still matches.
+	/// Note: The first run with it updates every check, to add the tag.
+	#[clap(long)]
+	spec_hash: bool,
+
+	/// Tags each check with the version of healthkube that last changed it, e.g. "hkver:0.1.0".
+	/// Note: A new version alone doesn't update the checks, the tag only changes along with something else.
+	#[clap(long)]
+	stamp_version: bool,
+
+	/// Re-fetches every created or updated check, and warns about any field that didn't end up as planned.
+	/// Note: Catches HealthChecks quietly coercing a value (e.g. normalising the timezone), at the cost of a read per check.
+	#[clap(long, conflicts_with = "dry-run")]
+	verify: bool,
+
+	/// Only synchronises the CronJobs that were modified within this window (e.g. "90s", "30m", "12h" or "7d").
+	/// Note: A job's last modification is the latest of its creationTimestamp and its managedFields' times.
+	/// Note: The tags are still derived from every job, so they don't shift between runs.
+	#[clap(long, value_name = "DURATION", parse(try_from_str = parse_duration))]
+	modified_since: Option<u64>,
+
+	/// What to do when CronJobs in different namespaces (or contexts) would share a check in the same project.
+	/// Note: "warn" keeps the last one with a warning, while "first" and "last" pick quietly.
+	/// Note: Only checked when the checks are identified by their name, and not with --key-by-uid.
+	#[clap(long, arg_enum, default_value = "warn")]
+	on_name_collision: CollisionPolicy,
+
+	/// Instead of a line per job, prints a single line per namespace, e.g. "context/namespace: created=2 updated=3 ...".
+	/// Note: Under --dry-run, the counts are what would happen.
+	#[clap(long)]
+	summary_only: bool,
+
+	/// Warns (on stderr) about every namespace without any matching CronJobs, which can hint at RBAC or selector mistakes.
+	#[clap(long)]
+	warn_empty: bool,
+
 	/// Kubernetes contexts with namespaces.
 	/// Pattern: context-name:namespace
+	/// Note: "context-name:*" selects every namespace in the context, and "context-name:team-*" (glob) or "context-name:~^team-" (regex) those matching.
+	/// Note: The context can be a pattern too, e.g. "prod-*:billing" (glob) or "~^prod-:billing" (regex) selects "billing" in every matching context.
+	/// Note: "-" reads more targets from stdin, one per line (blank lines and "#" comments are ignored).
+	/// Note: The check defaults can be overridden per target, e.g. "context-name:namespace;timeout=300;grace=60;tz=America/New_York".
 	#[clap(required = true)]
 	targets: Vec<String>,
 }
 
-#[derive(ClapArgs, Debug)]
+#[derive(ArgEnum, Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+enum OutputFormat {
+	Text,
+	Json,
+	Ndjson,
+}
+
+#[derive(ArgEnum, Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+enum ReplaceStrategy {
+	/// Sends back the whole CronJob.
+	Replace,
+	/// Sends a JSON Patch of only the env changes.
+	Patch,
+	/// Server-side applies the env, owned by the --field-manager.
+	Apply,
+}
+
+#[derive(ArgEnum, Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+enum CollisionPolicy {
+	Error,
+	Warn,
+	First,
+	Last,
+}
+
+#[derive(ArgEnum, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+enum UniqueKey {
+	Name,
+	Tags,
+}
+
+impl UniqueKey {
+	fn field(self) -> &'static str {
+		match self {
+			UniqueKey::Name => "name",
+			UniqueKey::Tags => "tags",
+		}
+	}
+}
+
+/// A HealthChecks project that a context's checks are sent to, instead of the --hc-key one.
+#[derive(Debug, Clone, serde::Serialize)]
+struct ContextProject {
+	context: String,
+	#[serde(serialize_with = "serialize_redacted")]
+	key: String,
+	url: Option<String>,
+}
+
+/// The HealthChecks clients, and which target uses which.
+struct Projects {
+	default: Option<ManageClient>,
+	/// Only set for --hc-project-token.
+	channels: Option<ManageClient>,
+	/// Keyed by "context:namespace" or "context", from --hc-context-key.
+	by_target: HashMap<String, ManageClient>,
+	/// The last few characters of each key (`None` is --hc-key's), so the one in use can be told apart in the trace.
+	hints: HashMap<Option<String>, String>,
+}
+
+impl Projects {
+	fn new(args: &Args) -> Result<Projects> {
+		let hc = &args.hc;
+		// Offline, there's no client, so the integrations are taken as given.
+		let (hc_client, channels_client) = match args.offline {
+			Some(_) => (None, None),
+			None => (Some(hc_client(hc)?), channels_client(hc)?),
+		};
+		Ok(Projects {
+			by_target: match args.offline {
+				Some(_) => HashMap::new(),
+				None => context_clients(hc)?,
+			},
+			hints: hc.key.iter()
+				.map(|key| (None, key_hint(key)))
+				.chain(hc.context_projects.iter().map(|project| (Some(project.context.clone()), key_hint(&project.key))))
+				.collect(),
+			channels: channels_client,
+			default: hc_client,
+		})
+	}
+
+	/// The client for listing the integrations/channels.
+	fn channels(&self) -> Option<&ManageClient> {
+		self.channels.as_ref().or(self.default.as_ref())
+	}
+
+	fn client(&self, plan: &plan::NamespacePlan) -> Option<&ManageClient> {
+		match self.scope(plan) {
+			Some(scope) => self.by_target.get(&scope),
+			None => self.default.as_ref(),
+		}
+	}
+
+	/// Identifies the project a namespace uses, so every project is only queried once: `None` is the --hc-key one.
+	///
+	/// A "context:namespace" mapping takes precedence over one for the whole context.
+	fn scope(&self, plan: &plan::NamespacePlan) -> Option<String> {
+		[format!("{}:{}", plan.context, plan.namespace), plan.context.clone()]
+			.into_iter()
+			.find(|scope| self.by_target.contains_key(scope))
+	}
+
+	/// Describes the key a namespace uses, without giving it away.
+	fn describe(&self, plan: &plan::NamespacePlan) -> String {
+		let scope = self.scope(plan);
+		let hint = self.hints.get(&scope).map(String::as_str).unwrap_or("(none)");
+		match scope {
+			Some(scope) => format!("the \"{}\" key {}", scope, hint),
+			None => format!("the --hc-key key {}", hint),
+		}
+	}
+
+	/// The distinct projects used by the plans, with their clients.
+	fn used_by<'a>(&'a self, plans: &[plan::NamespacePlan]) -> Vec<(Option<String>, &'a ManageClient)> {
+		let mut used: Vec<(Option<String>, &ManageClient)> = vec![];
+		for plan in plans {
+			let scope = self.scope(plan);
+			if used.iter().any(|(used, _)| *used == scope) {
+				continue;
+			}
+			if let Some(client) = self.client(plan) {
+				used.push((scope, client));
+			}
+		}
+		used
+	}
+}
+
+/// Only the last 4 characters of a key, e.g. "***abcd".
+fn key_hint(key: &str) -> String {
+	let chars: Vec<_> = key.chars().collect();
+	let tail: String = chars[chars.len().saturating_sub(4)..].iter().collect();
+	format!("***{}", tail)
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+	/// Prints a completion script for the given shell to stdout.
+	Completions {
+		#[clap(arg_enum)]
+		shell: Shell,
+	},
+
+	/// Checks the HealthChecks credentials and that every target is reachable, without changing anything.
+	Validate {
+		/// Kubernetes contexts with namespaces.
+		/// Pattern: context-name:namespace
+		/// Note: "-" reads more targets from stdin, one per line.
+		/// Note: The context can be a pattern, e.g. "prod-*:billing" (glob) or "~^prod-:billing" (regex).
+		#[clap(required = true)]
+		targets: Vec<String>,
+	},
+
+	/// Pauses the existing checks of every CronJob in the targets, e.g. for a maintenance window.
+	/// Note: Under --dry-run, it only lists the checks it would pause.
+	/// Note: A paused check resumes with its job's next ping (unless it's set to be resumed manually).
+	Pause {
+		/// Kubernetes contexts with namespaces, like the synchronisation's targets.
+		/// Note: "-" reads more targets from stdin, one per line.
+		#[clap(required = true)]
+		targets: Vec<String>,
+	},
+}
+
+#[derive(ClapArgs, Debug, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
 #[clap(next_help_heading = "HEALTHCHECKS")]
 struct HealthChecksInfo {
 	/// The read/write healthchecks' api key.
 	#[clap(long = "hc-key", env = "HC_API_KEY")]
+	#[serde(serialize_with = "serialize_secret")]
 	key: Option<String>,
 
+	/// The project token used to list the integrations/channels, for instances that scope it apart from the api key.
+	/// Note: Falls back to --hc-key.
+	#[clap(long = "hc-project-token", env = "HC_PROJECT_TOKEN")]
+	#[serde(serialize_with = "serialize_secret")]
+	project_token: Option<String>,
+
 	/// Where to find the Healthchecks instance.
 	#[clap(long = "hc-url", env = "HC_API_URL")]
 	url: Option<String>,
 
+	/// Reads the api key and url from the "key" and "url" fields of this Kubernetes Secret, e.g. "monitoring/healthchecks".
+	/// Note: It's read with the current kubeconfig context, or the pod's service account when running in-cluster.
+	/// Note: Its values are used instead of --hc-key and --hc-url.
+	#[clap(long = "hc-credentials-secret", value_name = "NAMESPACE/NAME")]
+	credentials_secret: Option<String>,
+
+	/// Sends the checks of a context (or a single "context:namespace") to its own project,
+	/// e.g. "prod=<key>", "prod:billing=<key>" or "prod=<key>@https://hc.example.com/api/v1/".
+	/// Note: Unmapped targets use --hc-key, and a mapping without a url uses --hc-url.
+	/// Note: At -vvv, the last few characters of the key each namespace uses are logged.
+	/// Note: The integrations are still resolved against the --hc-key project.
+	#[clap(
+		long = "hc-context-key",
+		env = "HC_CONTEXT_KEYS",
+		value_name = "CONTEXT[:NAMESPACE]=KEY[@URL]",
+		value_delimiter = ',',
+		parse(try_from_str = parse_context_project),
+	)]
+	context_projects: Vec<ContextProject>,
+
 	/// Also known as channels.
-	/// All of the integrations/channels to assign to all newly created checks.
+	/// All of the integrations/channels (ids or names) to assign to all newly created checks.
+	/// Note: Without any of the integration flags, the checks keep HealthChecks' default channels.
 	#[clap(long)]
 	integrations: Vec<String>,
 
+	/// Reads more integrations/channels from this file, one id or name per line (blank lines and "#" comments are ignored).
+	/// Note: They're merged with --integrations.
+	#[clap(long, value_name = "PATH", conflicts_with = "all-integrations")]
+	integrations_file: Option<PathBuf>,
+
+	/// Keeps the channels an existing check already has (e.g. added in the UI), adding the integrations to them instead of replacing them.
+	/// Note: The existing channels are only known when the api key can read them.
+	#[clap(long, conflicts_with = "no-integrations")]
+	append_channels: bool,
+
+	/// Explicitly assigns no integrations/channels, instead of HealthChecks' default.
+	#[clap(long, conflicts_with_all = &["integrations", "integrations-file", "all-integrations"])]
+	no_integrations: bool,
+
 	/// Uses all integrations/channels currently registered for the project.
 	#[clap(long, conflicts_with = "integrations")]
 	all_integrations: bool,
 
+	/// Only warn about integrations/channels that aren't registered for the project,
+	/// instead of refusing to run.
+	#[clap(long)]
+	allow_unknown_integrations: bool,
+
 	/// The timezone to use for all newly created checks.
 	#[clap(long, default_value = "Europe/Berlin")]
 	timezone: String,
@@ -75,249 +563,1188 @@ struct HealthChecksInfo {
 	#[clap(long, default_value_t = 60 * 60 * 8)]
 	grace: i32,
 
+	/// The lowest timeout (in seconds) any check gets, even when --timeout or a target's override asks for less.
+	#[clap(long, value_name = "SECS")]
+	min_timeout: Option<i32>,
+
+	/// The lowest grace period (in seconds) any check gets, even when --grace or a target's override asks for less.
+	/// Note: Frequent jobs need some slack for the usual jitter, before they're reported as late.
+	#[clap(long, value_name = "SECS")]
+	min_grace: Option<i32>,
+
+	/// Which fields identify an existing check, when deciding whether to create or update it.
+	#[clap(long, arg_enum, value_delimiter = ',', default_value = "name")]
+	unique_by: Vec<UniqueKey>,
+
+	/// Leaves out the unique fields entirely, so every run creates new checks rather than updating the existing ones.
+	/// Note: This duplicates the checks on each run, so it's only for projects that tell them apart some other way (e.g. by tags).
+	#[clap(long, conflicts_with = "unique-by")]
+	no_unique: bool,
+
 	/// Clears all existing checks.
 	/// Note, it won't just remove those added.
 	/// It will remove all of them. Completely.
-	/// Without confirmation.
+	/// Note: They're all listed first, and only deleted once confirmed (or with --assume-yes).
 	#[clap(long)]
 	clear_existing_checks: bool,
 }
 
 #[tokio::main(flavor = "current_thread")]
-async fn main() -> Result<()> {
-	let Args {
-		hc,
-		dry_run,
-		rank,
-		env_key,
-		targets,
-	} = Parser::parse();
-
-	let timezone = Some(hc.timezone);
-	let timeout = Some(hc.timeout);
-	let grace = Some(hc.grace);
-
-	let hc_client = {
-		let key = hc.key.context(
-			"Unable to locate the HealthChecks API Key. [Try setting a env var: \"HC_API_KEY\"]",
-		)?;
-		let url = hc.url.context(
-			"Unable to locate the HealthChecks API URL. [Try setting a env var: \"HC_API_URL\"]",
-		)?;
-
-		healthchecks::manage::get_client_with_url(key, None, url)
-			.context("Unable to construct HealthChecks client")?
-	};
+async fn main() {
+	let mut args = Args::parse();
 
-	let integrations = {
-		let mut integrations = if hc.all_integrations {
-			hc_client
-				.get_channels()?
-				.into_iter()
-				.map(|channel| channel.id)
-				.collect()
-		} else {
-			hc.integrations
+	let result = async {
+		let targets = match &mut args.command {
+			Some(Command::Validate { targets } | Command::Pause { targets }) => targets,
+			_ => &mut args.targets,
 		};
+		read_stdin_targets(targets, std::io::stdin().lock())?;
+		read_credentials_secret(&mut args.hc).await?;
+		read_integrations_file(&mut args.hc)?;
+		run(&args).await
+	}.await;
 
-		integrations.sort_unstable();
-		integrations.dedup();
+	if let Err(err) = result {
+		let message = redact::redact(&format!("{:?}", err), &args.hc.secrets());
+		eprintln!("Error: {}", message);
+		std::process::exit(1);
+	}
+}
+
+async fn run(args: &Args) -> Result<()> {
+	if let Some(path) = &args.output_file {
+		output::tee(path).with_context(|| format!("Unable to create the output file {}", path.display()))?;
+	}
+
+	match &args.command {
+		Some(Command::Completions { shell }) => {
+			let mut command = Args::command();
+			let name = command.get_name().to_string();
+			clap_complete::generate(*shell, &mut command, name, &mut std::io::stdout());
+			return Ok(());
+		}
+		Some(Command::Validate { targets }) => {
+			return validate::validate(args, targets).await;
+		}
+		Some(Command::Pause { .. }) | None => {}
+	}
+
+	if args.dump_effective_config {
+		println!("{}", serde_json::to_string_pretty(args)?);
+		return Ok(());
+	}
 
-		let integrations = integrations.join(",");
+	if !ENV_VALUE_PLACEHOLDERS.iter().any(|placeholder| args.env_value_template.contains(placeholder)) {
+		anyhow::bail!(
+			"\"--env-value-template\" must contain at least one of: {}",
+			ENV_VALUE_PLACEHOLDERS.join(", "),
+		);
+	}
 
-		println!("Using integrations: {}", integrations);
+	if args.output == OutputFormat::Ndjson && args.dry_run {
+		anyhow::bail!("\"--output ndjson\" streams the outcomes of a synchronisation, so it can't be used with \"--dry-run\" [Use \"--output json\" for the plan]");
+	}
+
+	if !args.remove_envs.is_empty() && args.replace_strategy == ReplaceStrategy::Apply {
+		anyhow::bail!("\"--remove-env\" can't be used with \"--replace-strategy apply\", as an apply only removes the env it owns [Use \"--replace-strategy patch\"]");
+	}
 
-		Some(integrations)
+	if args.remove_managed_env && args.replace_strategy == ReplaceStrategy::Apply {
+		anyhow::bail!("\"--remove-managed-env\" can't be used with \"--replace-strategy apply\", as an apply only removes the env it owns [Use \"--replace-strategy patch\"]");
+	}
+
+	if args.confirm_each && !args.assume_yes && !std::io::stdin().is_terminal() {
+		anyhow::bail!("\"--confirm-each\" needs an interactive terminal. [Use \"--dry-run\" to preview the changes, or \"--assume-yes\" to make them]");
+	}
+
+	let hc = &args.hc;
+	let choice = if args.no_color { ColorChoice::Never } else { args.color };
+	let color = style::use_color(choice, std::io::stdout().is_terminal());
+
+	// It only touches the CronJobs, so HealthChecks isn't needed (nor configured) for it.
+	if args.remove_managed_env && args.command.is_none() {
+		let mut summary = Summary::new(args.output == OutputFormat::Ndjson);
+		let plans = plan::plan(args, &args.targets, &NewCheck::default()).await?;
+		apply::remove_managed_env(args, plans, color, &mut summary).await?;
+		report(args, &summary)?;
+		return check_strict(args);
+	}
+
+	let projects = Projects::new(args)?;
+	if let Some(Command::Pause { targets }) = &args.command {
+		let mut summary = Summary::new(args.output == OutputFormat::Ndjson);
+		// Only the names (and identity tags) matter, to find the checks.
+		let plans = plan::plan(args, targets, &NewCheck::default()).await?;
+		apply::pause(args, &projects, plans, color, &mut summary).await?;
+		report(args, &summary)?;
+		return check_strict(args);
+	}
+
+	let integrations = default_integrations(args, &projects).await?;
+
+	if hc.no_unique {
+		warning!("--no-unique is set, so every run creates new checks (and duplicates the existing ones)");
+	}
+
+	let code = run_sync(args, &projects, &default_check(hc, integrations), color).await?;
+	if code != 0 {
+		std::process::exit(code);
+	}
+
+	Ok(())
+}
+
+/// Synchronises (cut off by --run-timeout), and reports what was done.
+///
+/// It's the exit code that's returned: RUN_TIMEOUT_EXIT_CODE when it was cut off, or DETECT_CHANGES_EXIT_CODE when there were pending changes.
+async fn run_sync(args: &Args, projects: &Projects, default_check: &NewCheck, color: bool) -> Result<i32> {
+	let mut summary = Summary::new(args.output == OutputFormat::Ndjson);
+
+	let run = sync(args, projects, default_check, color, &mut summary);
+	let result = match args.run_timeout {
+		Some(secs) => {
+			let outcome = tokio::time::timeout(Duration::from_secs(secs), run).await;
+			let Ok(result) = outcome else {
+				eprintln!("Error: The synchronisation didn't finish within {} second(s)", secs);
+				report(args, &summary)?;
+				return Ok(RUN_TIMEOUT_EXIT_CODE);
+			};
+			result
+		}
+		None => run.await,
 	};
+	let pending = result?;
+
+	report(args, &summary)?;
+	check_strict(args)?;
 
-	if !dry_run && hc.clear_existing_checks {
-		hc_client
-			.get_checks()?
+	if args.detect_changes && pending > 0 {
+		return Ok(DETECT_CHANGES_EXIT_CODE);
+	}
+
+	Ok(0)
+}
+
+/// The integrations that every check gets, unless its annotation says otherwise: `None` leaves them to HealthChecks' default, and `Some("")` is none at all.
+///
+/// The names are resolved to ids, when the integrations can be listed.
+async fn default_integrations(args: &Args, projects: &Projects) -> Result<Option<String>> {
+	let hc = &args.hc;
+	let channels_client = projects.channels();
+
+	let mut integrations = if hc.all_integrations {
+		let Some(channels_client) = channels_client else {
+			anyhow::bail!("\"--all-integrations\" can't be resolved with \"--offline\"");
+		};
+		trace::hc(args, channels_client, "GET", "channels", |client| client.get_channels()).await?
 			.into_iter()
-			.filter_map(|check| check.id())
-			.for_each(|id| {
-				println!("Deleting: {}", id);
-				hc_client
-					.delete(&id)
-					.context(format!("Unable to delete healthcheck: {}", id))
-					.unwrap();
-			});
-	}
-
-	let kubeconfig = Kubeconfig::read().unwrap();
-	let mut opts = KubeConfigOptions::default();
-
-	for target in targets {
-		let (context, namespaces) = match target.split_once(':') {
-			Some(values) => values,
-			None => (&*target, "default"),
+			.map(|channel| channel.id)
+			.collect()
+	} else {
+		// Names are resolved to ids, and anything that can't be is still used as given (HealthChecks accepts names too).
+		match channels_client.filter(|_| !hc.integrations.is_empty()) {
+			Some(channels_client) => match trace::hc(args, channels_client, "GET", "channels", |client| client.get_channels()).await {
+				Ok(channels) => {
+					let (integrations, unknown) = resolve_integrations(&hc.integrations, &channels);
+					if !unknown.is_empty() {
+						let unknown = unknown.join(", ");
+						if !hc.allow_unknown_integrations {
+							anyhow::bail!(
+								"Unknown integrations: {}. [Use \"--allow-unknown-integrations\" to continue anyway]",
+								unknown,
+							);
+						}
+						warning!("Unknown integrations: {}", unknown);
+					}
+					integrations
+				}
+				Err(err) => {
+					let message = redact::redact(&err.to_string(), &hc.secrets());
+					warning!("Unable to list the integrations, so they aren't validated: {}", message);
+					hc.integrations.clone()
+				}
+			},
+			None => hc.integrations.clone(),
+		}
+	};
+
+	integrations.sort_unstable();
+	integrations.dedup();
+
+	// An empty list means "no channels" to HealthChecks, so only send it when asked to.
+	let integrations = integrations.join(",");
+	let integrations = match (integrations.is_empty(), hc.no_integrations) {
+		(true, false) => None,
+		_ => Some(integrations),
+	};
+
+	if args.output == OutputFormat::Text {
+		let description = match &integrations {
+			None => "(default)",
+			Some(integrations) if integrations.is_empty() => "(none)",
+			Some(integrations) => integrations,
 		};
-		println!("Context: {}", context);
-		opts.context = Some(context.into());
+		say!("Using integrations: {}", description);
+	}
 
-		let config = Config::from_custom_kubeconfig(kubeconfig.clone(), &opts)
-			.await
-			.unwrap();
-
-		let default_check = NewCheck {
-			timeout,
-			grace,
-			tz: timezone.clone(),
-			channels: integrations.clone(),
-			..Default::default()
+	Ok(integrations)
+}
+
+/// Prints what happened during the run, or under --output json, the outcome of every job.
+///
+/// A dry-run has already printed its plan instead.
+fn report(args: &Args, summary: &Summary) -> Result<()> {
+	match args.output {
+		OutputFormat::Text => {
+			summary.print();
+			if args.verbose > 0 {
+				trace::print_timings();
+			}
+		}
+		OutputFormat::Json if args.dry_run && !args.remove_managed_env => {}
+		OutputFormat::Json => {
+			let document = report::ResultDocument::new(summary);
+			println!("{}", to_json(args, &document)?);
+		}
+		// Every outcome has already been streamed.
+		OutputFormat::Ndjson => {}
+	}
+
+	Ok(())
+}
+
+/// Fails under --strict, when any warning was printed.
+fn check_strict(args: &Args) -> Result<()> {
+	let warnings = output::warnings();
+	if args.strict && warnings > 0 {
+		anyhow::bail!("{} warning(s) were printed, which \"--strict\" treats as errors", warnings);
+	}
+	Ok(())
+}
+
+/// Serialises a JSON document, on a single line unless asked for --json-pretty.
+fn to_json<T: serde::Serialize>(args: &Args, value: &T) -> serde_json::Result<String> {
+	if args.json_pretty {
+		serde_json::to_string_pretty(value)
+	} else {
+		serde_json::to_string(value)
+	}
+}
+
+/// The placeholders that --env-value-template substitutes.
+const ENV_VALUE_PLACEHOLDERS: [&str; 2] = ["{id}", "{ping_url}"];
+
+/// The exit code used when --run-timeout expires, mirroring timeout(1).
+const RUN_TIMEOUT_EXIT_CODE: i32 = 124;
+
+/// The exit code used by --detect-changes when something would change, mirroring "terraform plan -detailed-exitcode".
+const DETECT_CHANGES_EXIT_CODE: i32 = 2;
+
+async fn sync(
+	args: &Args,
+	projects: &Projects,
+	default_check: &NewCheck,
+	color: bool,
+	summary: &mut Summary,
+) -> Result<usize> {
+	let mut plans = plan::plan(args, &args.targets, default_check).await?;
+	resolve_channel_annotations(args, projects, &mut plans).await?;
+	resolve_name_collisions(args, projects, &mut plans)?;
+	if args.spec_hash {
+		plan::add_spec_hashes(&mut plans);
+	}
+	if args.stamp_version {
+		plan::add_version_tags(&mut plans);
+	}
+
+	let wants_document = args.dry_run && (args.output == OutputFormat::Json || args.plan_out.is_some() || args.plan_since.is_some());
+	let wants_checks = args.max_checks.is_some() || wants_document || args.detect_changes;
+
+	// Clearing the existing checks means every planned check will be created.
+	// Each job is matched within its own project, the same way the synchronisation does (e.g. by its identity tag).
+	let checks = if !args.hc.clear_existing_checks && wants_checks {
+		apply::existing_checks(args, projects, &plans).await?
+	} else {
+		HashMap::new()
+	};
+	let existing = |plan: &plan::NamespacePlan, job: &plan::PlannedJob| {
+		let checks = checks.get(&projects.scope(plan)).map(Vec::as_slice).unwrap_or_default();
+		apply::existing_check(args, checks, job)
+	};
+
+	// Only counted under --detect-changes, which requires --dry-run.
+	let mut pending = 0;
+	if args.detect_changes {
+		pending += plans.iter()
+			.flat_map(|plan| plan.jobs.iter().map(move |job| (plan, job)))
+			.filter(|(plan, job)| !existing(plan, job).is_some_and(|check| plan::is_up_to_date(&job.check, check)))
+			.count();
+	}
+
+	if let Some(max_checks) = args.max_checks {
+		let creations = plans.iter()
+			.flat_map(|plan| plan.jobs.iter().map(move |job| (plan, job)))
+			.filter(|(plan, job)| existing(plan, job).is_none())
+			.count();
+
+		if creations > max_checks {
+			let message = format!(
+				"The synchronisation would create {} checks, more than the allowed {}.",
+				creations,
+				max_checks,
+			);
+			if args.dry_run {
+				warning!("{}", message);
+			} else if !args.force {
+				anyhow::bail!("{} [Use \"--force\" to continue anyway]", message);
+			}
+		}
+	}
+
+	// Every project that's synchronised to is cleared.
+	// The exact checks that would be deleted are listed first, or under --dry-run, instead.
+	let mut deletions = vec![];
+	for (_, hc_client) in projects.used_by(&plans).into_iter().filter(|_| args.hc.clear_existing_checks) {
+		let checks = trace::hc(args, hc_client, "GET", "checks", |client| client.get_checks()).await?;
+		deletions.extend(checks.into_iter().filter_map(|check| Some((hc_client, check.id()?, check))));
+	}
+	if args.dry_run {
+		pending += deletions.len();
+	}
+
+	if wants_document {
+		let planned_deletions = deletions.iter()
+			.map(|(_, id, check)| report::PlannedDeletion {
+				name: &check.name,
+				check: id,
+			})
+			.collect();
+		let document = report::PlanDocument::new(&plans, planned_deletions, existing);
+		let json = to_json(args, &document)?;
+
+		// Read before --plan-out can overwrite it.
+		let previous = match &args.plan_since {
+			Some(path) => Some(read_saved_plan(path)?),
+			None => None,
 		};
 
-		for namespace in namespaces.split(',') {
-			println!("\tNamespace: {}", namespace);
+		if let Some(path) = &args.plan_out {
+			std::fs::write(path, &json)
+				.with_context(|| format!("Unable to write the plan to {}", path.display()))?;
+		}
+		if args.output == OutputFormat::Json {
+			println!("{}", json);
+			return Ok(pending);
+		}
+		if let Some(previous) = previous {
+			let current: report::SavedPlan = serde_json::from_str(&json)?;
+			print_changes(&previous.changes(&current));
+			return Ok(pending);
+		}
+	}
+
+	// The whole set is previewed first, and has to be confirmed as a whole, unless every deletion is asked about anyway.
+	if !deletions.is_empty() {
+		for (_, id, check) in &deletions {
+			// Kept off stdout, when that's JSON.
+			match args.output {
+				OutputFormat::Text => say!("Would delete: {} (\"{}\")", check.name, id),
+				OutputFormat::Json | OutputFormat::Ndjson => eprintln!("Would delete: {} (\"{}\")", check.name, id),
+			}
+		}
+		if args.dry_run {
+			deletions.clear();
+		} else if !args.assume_yes && !args.confirm_each {
+			confirm_deletions(deletions.len(), std::io::stdin().is_terminal(), std::io::stdin().lock(), std::io::stderr())?;
+		}
+	}
 
-			let kube_client = Client::try_from(config.clone()).unwrap();
-			let kube_api: kube::Api<CronJob> = kube::Api::namespaced(kube_client, namespace);
-			let mut jobs = kube_api.list(&ListParams::default()).await?.items;
+	for (hc_client, id, check) in deletions {
+		if !confirmed(args, &format!("Delete \"{}\" (\"{}\")?", check.name, id)) {
+			continue;
+		}
 
-			jobs.retain(|job| {
-				if let Some(name) = &job.metadata.name {
-					name == "sales-au-job-cleanup-shared-products-job"
-				} else {
-					false
-				}
-			});
+		let check_id = id.clone();
+		trace::hc(args, hc_client, "DELETE", &format!("checks/{}", id), move |client| client.delete(&check_id))
+			.await
+			.with_context(|| format!("Unable to delete healthcheck: {}", id))?;
+		if args.output == OutputFormat::Text {
+			say!("{}(\"{}\")", Status::Deleted.paint(color), id);
+		}
+		summary.record(Outcome {
+			context: None,
+			namespace: None,
+			name: check.name,
+			check: Some(id),
+			action: Action::Deleted,
+		});
+	}
 
-			let definitions: Vec<_> = jobs.iter_mut()
-				.filter_map(describe)
-				.collect();
+	apply::apply(args, projects, plans, color, summary).await?;
 
-			let common_tags = {
-				let mut common_tags: HashMap<&str, u8> = definitions.iter()
-					.flat_map(|(name, _, _)| name.split('-'))
-					.fold(HashMap::new(), |mut acc, item| {
-						*acc.entry(item).or_default() += 1;
-						acc
-					});
-
-				common_tags.remove("job");
-				if rank > 0 {
-					common_tags.retain(|_, v| *v >= rank);
-				}
+	Ok(pending)
+}
 
-				common_tags
-			};
+/// Reads the previous plan for --plan-since, where a missing file is an empty plan.
+fn read_saved_plan(path: &std::path::Path) -> Result<report::SavedPlan> {
+	let json = match std::fs::read(path) {
+		Ok(json) => json,
+		Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(report::SavedPlan::default()),
+		Err(err) => return Err(err).with_context(|| format!("Unable to read the previous plan from {}", path.display())),
+	};
+	serde_json::from_slice(&json)
+		.with_context(|| format!("Unable to parse the previous plan in {}", path.display()))
+}
 
-			definitions.into_iter()
-				.filter_map(|(name, schedule, containers)| {
-					let tags: String = name
-						.split('-')
-						.filter(|segment| common_tags.contains_key(*segment))
-						.intersperse(" ")
-						.collect();
-
-					if dry_run {
-						println!("\t\t: {: <50} -> [{}]", name, &tags);
-						return None;
-					}
+/// Prints what changed since the previous plan, under --plan-since.
+fn print_changes(changes: &[(String, report::Change)]) {
+	if changes.is_empty() {
+		say!("No changes since the previous plan");
+		return;
+	}
 
-					let (status, check_id) = {
-						let new_check = NewCheck {
-							name: Some(name.into()),
-							schedule: Some(schedule.into()),
-							tags: Some(tags),
-							unique: Some(vec![String::from("name")]),
-							..default_check.clone()
-						};
-
-						let (status, check) = hc_client.upsert_check(new_check).ok()?;
-						let check_id = check.id()?;
-
-						let status = match status {
-							healthchecks::manage::UpsertResult::Created => "Created",
-							healthchecks::manage::UpsertResult::Updated => "Updated",
-						};
-
-						(status, check_id)
-					};
-
-					println!("\t\t: {: <50} -> {}(\"{}\")", name, status, check_id);
-
-					let env_key = match env_key.as_deref() {
-						Some(value) => value,
-						None => {
-							// Skip updating kubernetes, if no env_key was defined.
-							containers.clear();
-							return None;
-						}
-					};
-
-					containers.retain_mut(|container| {
-						let Some(env) = &mut container.env else {
-							return false;
-						};
-
-						let item = env.iter_mut()
-							.find(|env| env.name == env_key)
-							.and_then(|var| var.value.as_mut());
-
-						match item {
-							Some(item) => {
-								let need_to_update = *item != check_id;
-								if need_to_update {
-									*item = check_id.clone();
-								}
-								need_to_update
-							}
-							None => {
-								let var = EnvVar {
-									name: env_key.into(),
-									value: Some(check_id.clone()),
-									..Default::default()
-								};
-								env.push(var);
-								true
-							}
-						}
-					});
+	say!("Changes since the previous plan:");
+	for (job, change) in changes {
+		match change {
+			report::Change::New(action) => say!("\t+ {: <10} {}", action, job),
+			report::Change::Resolved(action) => say!("\t- {: <10} {} (no longer needed)", action, job),
+		}
+	}
+}
 
-					if containers.is_empty() {
-						return None;
-					}
+/// Replaces the integrations/channels of every job with those of its annotation, resolving any names to ids.
+///
+/// Offline, the annotations are taken as given.
+/// The channels are only listed when any job has an annotation.
+async fn resolve_channel_annotations(args: &Args, projects: &Projects, plans: &mut [plan::NamespacePlan]) -> Result<()> {
+	let annotated = plans.iter().flat_map(|plan| &plan.jobs).any(|job| job.channels().is_some());
+	// HealthChecks accepts names too, so when the channels can't be listed, the annotations are taken as given.
+	let channels = match projects.channels() {
+		Some(client) if annotated => match trace::hc(args, client, "GET", "channels", |client| client.get_channels()).await {
+			Ok(channels) => Some(channels),
+			Err(err) => {
+				let message = redact::redact(&err.to_string(), &args.hc.secrets());
+				warning!("Unable to list the integrations, so the {} annotations are used as given: {}", plan::CHANNELS_ANNOTATION, message);
+				None
+			}
+		},
+		_ => None,
+	};
 
-					Some(1)
-				})
-				.count();
+	for job in plans.iter_mut().flat_map(|plan| &mut plan.jobs) {
+		let Some(requested) = job.channels() else {
+			continue;
+		};
 
-			if dry_run {
+		let mut ids = vec![];
+		for channel in requested {
+			let Some(channels) = &channels else {
+				ids.push(channel.to_string());
 				continue;
+			};
+			match channels.iter().find(|known| known.id == channel || known.name == channel) {
+				Some(found) => ids.push(found.id.clone()),
+				None if args.hc.allow_unknown_integrations => {
+					warning!("\"{}\" has an unknown integration in {}: {}", job.name(), plan::CHANNELS_ANNOTATION, channel);
+					ids.push(channel.to_string());
+				}
+				None => anyhow::bail!(
+					"\"{}\" has an unknown integration in {}: {}. [Use \"--allow-unknown-integrations\" to continue anyway]",
+					job.name(),
+					plan::CHANNELS_ANNOTATION,
+					channel,
+				),
 			}
+		}
+		ids.sort_unstable();
+		ids.dedup();
+		job.check.channels = Some(ids.join(","));
+	}
 
-			for mut job in jobs {
-				let Some((_, _, containers)) = describe(&mut job) else {
-					continue;
-				};
-				if containers.is_empty() {
-					continue;
-				}
+	Ok(())
+}
 
-				let params = PostParams::default();
-				let _ = kube_api.replace(&job.name(), &params, &job).await?;
+/// Keeps only one of the jobs that share a check name in the same project, according to --on-name-collision.
+///
+/// Otherwise they'd all upsert the same check, and it'd flap between their schedules.
+fn resolve_name_collisions(args: &Args, projects: &Projects, plans: &mut [plan::NamespacePlan]) -> Result<()> {
+	if args.key_by_uid || args.hc.no_unique || !args.hc.unique_by.contains(&UniqueKey::Name) {
+		return Ok(());
+	}
+
+	// Only jobs going to the same project can end up on the same check.
+	let mut occurrences = HashMap::new();
+	for (plan_index, plan) in plans.iter().enumerate() {
+		let scope = projects.scope(plan);
+		for (job_index, job) in plan.jobs.iter().enumerate() {
+			occurrences.entry((scope.clone(), job.name())).or_insert_with(Vec::new).push((plan_index, job_index));
+		}
+	}
+
+	let mut dropped = vec![];
+	for ((_, name), locations) in occurrences.into_iter().filter(|(_, locations)| locations.len() > 1) {
+		let described = locations.iter()
+			.map(|(plan, _)| format!("{}:{}", plans[*plan].context, plans[*plan].namespace))
+			.collect::<Vec<_>>()
+			.join(", ");
+
+		let kept = match args.on_name_collision {
+			CollisionPolicy::Error => anyhow::bail!(
+				"\"{}\" is defined in more than one namespace: {}. [Use \"--on-name-collision\" to pick one]",
+				name,
+				described,
+			),
+			CollisionPolicy::Warn => {
+				warning!("\"{}\" is defined in more than one namespace: {}. Keeping the last one.", name, described);
+				locations.len() - 1
 			}
+			CollisionPolicy::First => 0,
+			CollisionPolicy::Last => locations.len() - 1,
+		};
+		dropped.extend(locations.into_iter().enumerate().filter(|(index, _)| *index != kept).map(|(_, location)| location));
+	}
+
+	// Remove from the back, so the remaining positions stay valid.
+	dropped.sort_unstable();
+	for (plan_index, job_index) in dropped.into_iter().rev() {
+		let plan = &mut plans[plan_index];
+		let job = plan.jobs.remove(job_index);
+		plan.skipped.push((job.name().to_string(), plan::SkipReason::NameCollision));
+	}
+
+	Ok(())
+}
+
+impl HealthChecksInfo {
+	/// Everything that must never show up in the output.
+	fn secrets(&self) -> Vec<&str> {
+		self.key.as_deref()
+			.into_iter()
+			.chain(self.project_token.as_deref())
+			.chain(self.context_projects.iter().map(|project| &*project.key))
+			.collect()
+	}
+}
+
+/// Fills in the api key and url from --hc-credentials-secret.
+async fn read_credentials_secret(hc: &mut HealthChecksInfo) -> Result<()> {
+	let Some(reference) = hc.credentials_secret.clone() else {
+		return Ok(());
+	};
+	let Some((namespace, name)) = reference.split_once('/') else {
+		anyhow::bail!("\"--hc-credentials-secret\" must look like \"namespace/name\", not \"{}\"", reference);
+	};
+
+	let client = kube::Client::try_default()
+		.await
+		.context("Unable to connect to Kubernetes, to read the HealthChecks credentials")?;
+	let (key, url) = read_credentials(client, namespace, name).await?;
+
+	hc.key = Some(key);
+	hc.url = Some(url);
+	Ok(())
+}
+
+/// Reads the api key and url from the "key" and "url" fields of the secret.
+async fn read_credentials(client: kube::Client, namespace: &str, name: &str) -> Result<(String, String)> {
+	let reference = format!("{}/{}", namespace, name);
+	let api: kube::Api<Secret> = kube::Api::namespaced(client, namespace);
+	let secret = api.get(name)
+		.await
+		.with_context(|| format!("Unable to read the HealthChecks credentials from the secret \"{}\"", reference))?;
+
+	let data = secret.data.unwrap_or_default();
+	let field = |key: &str| -> Result<String> {
+		let value = data.get(key)
+			.with_context(|| format!("The secret \"{}\" has no \"{}\" field", reference, key))?;
+		let value = String::from_utf8(value.0.clone())
+			.with_context(|| format!("The \"{}\" field of the secret \"{}\" isn't valid UTF-8", key, reference))?;
+		Ok(value.trim().to_string())
+	};
+
+	Ok((field("key")?, field("url")?))
+}
+
+fn hc_client(hc: &HealthChecksInfo) -> Result<ManageClient> {
+	let key = hc.key.clone().context(
+		"Unable to locate the HealthChecks API Key. [Try setting a env var: \"HC_API_KEY\"]",
+	)?;
+	let url = hc.url.clone().context(
+		"Unable to locate the HealthChecks API URL. [Try setting a env var: \"HC_API_URL\"]",
+	)?;
+
+	healthchecks::manage::get_client_with_url(key, None, url)
+		.context("Unable to construct HealthChecks client")
+}
+
+/// The clients for the projects of --hc-context-key, falling back to --hc-url.
+fn context_clients(hc: &HealthChecksInfo) -> Result<HashMap<String, ManageClient>> {
+	hc.context_projects.iter()
+		.map(|project| {
+			let url = project.url.clone().or_else(|| hc.url.clone()).with_context(|| format!(
+				"Unable to locate the HealthChecks API URL for \"{}\". [Try setting a env var: \"HC_API_URL\"]",
+				project.context,
+			))?;
+			let client = healthchecks::manage::get_client_with_url(project.key.clone(), None, url)
+				.with_context(|| format!("Unable to construct HealthChecks client for \"{}\"", project.context))?;
+			Ok((project.context.clone(), client))
+		})
+		.collect()
+}
+
+/// Parses a duration like "90", "90s", "30m", "12h" or "7d" into seconds.
+fn parse_duration(value: &str) -> Result<u64, String> {
+	let (number, unit) = match value.find(|c: char| !c.is_ascii_digit()) {
+		Some(index) => value.split_at(index),
+		None => (value, "s"),
+	};
+	let multiplier = match unit {
+		"s" => 1,
+		"m" => 60,
+		"h" => 60 * 60,
+		"d" => 24 * 60 * 60,
+		_ => return Err(format!("unknown unit \"{}\" [Expected one of: s, m, h, d]", unit)),
+	};
+	let number: u64 = number.parse().map_err(|_| format!("\"{}\" isn't a duration", value))?;
+	number.checked_mul(multiplier).ok_or_else(|| format!("\"{}\" is too long", value))
+}
+
+/// Parses "context=key" or "context=key@url", where the context can also be a "context:namespace".
+fn parse_context_project(value: &str) -> Result<ContextProject, String> {
+	let Some((context, project)) = value.split_once('=') else {
+		return Err("expected \"CONTEXT[:NAMESPACE]=KEY[@URL]\"".into());
+	};
+	let (key, url) = match project.split_once('@') {
+		Some((key, url)) => (key, Some(url.to_string())),
+		None => (project, None),
+	};
+	if context.is_empty() || key.is_empty() {
+		return Err("expected \"CONTEXT[:NAMESPACE]=KEY[@URL]\"".into());
+	}
+
+	Ok(ContextProject {
+		context: context.into(),
+		key: key.into(),
+		url,
+	})
+}
+
+fn serialize_redacted<S: serde::Serializer>(_: &str, serializer: S) -> Result<S::Ok, S::Error> {
+	serializer.serialize_str("***")
+}
+
+fn serialize_secret<S: serde::Serializer>(secret: &Option<String>, serializer: S) -> Result<S::Ok, S::Error> {
+	match secret {
+		Some(_) => serializer.serialize_some("***"),
+		None => serializer.serialize_none(),
+	}
+}
+
+fn serialize_regex<S: serde::Serializer>(regex: &Option<Regex>, serializer: S) -> Result<S::Ok, S::Error> {
+	match regex {
+		Some(regex) => serializer.serialize_some(regex.as_str()),
+		None => serializer.serialize_none(),
+	}
+}
+
+/// A client for listing the channels, if they're scoped to a separate project token.
+fn channels_client(hc: &HealthChecksInfo) -> Result<Option<ManageClient>> {
+	let (Some(token), Some(url)) = (hc.project_token.clone(), hc.url.clone()) else {
+		return Ok(None);
+	};
+
+	healthchecks::manage::get_client_with_url(token, None, url)
+		.map(Some)
+		.context("Unable to construct HealthChecks client for the project token")
+}
+
+/// The target that's replaced by the targets read from stdin.
+const STDIN_TARGET: &str = "-";
+
+/// Replaces the "-" target with the targets read from `reader`, one per line.
+fn read_stdin_targets(targets: &mut Vec<String>, reader: impl BufRead) -> Result<()> {
+	let Some(position) = targets.iter().position(|target| target == STDIN_TARGET) else {
+		return Ok(());
+	};
+	targets.retain(|target| target != STDIN_TARGET);
+
+	let mut read = vec![];
+	for line in reader.lines() {
+		let line = line.context("Unable to read the targets from stdin")?;
+		let line = line.trim();
+		if line.is_empty() || line.starts_with('#') {
+			continue;
 		}
+		read.push(line.to_string());
 	}
 
+	targets.splice(position..position, read);
+	if targets.is_empty() {
+		anyhow::bail!("No targets were read from stdin");
+	}
+	Ok(())
+}
+
+/// Adds the integrations read from --integrations-file to --integrations.
+fn read_integrations_file(hc: &mut HealthChecksInfo) -> Result<()> {
+	let Some(path) = &hc.integrations_file else {
+		return Ok(());
+	};
+	let file = std::fs::File::open(path)
+		.with_context(|| format!("Unable to read the integrations from {}", path.display()))?;
+
+	for line in std::io::BufReader::new(file).lines() {
+		let line = line.with_context(|| format!("Unable to read the integrations from {}", path.display()))?;
+		let line = line.trim();
+		if line.is_empty() || line.starts_with('#') {
+			continue;
+		}
+		hc.integrations.push(line.to_string());
+	}
+	Ok(())
+}
+
+/// Whether the change described by `question` should go ahead, asking on the terminal under --confirm-each.
+fn confirmed(args: &Args, question: &str) -> bool {
+	if !args.confirm_each || args.assume_yes {
+		return true;
+	}
+	// A failure to ask is taken as a "no".
+	confirm(question, std::io::stdin().lock(), std::io::stderr()).unwrap_or(false)
+}
+
+/// Asks to delete all of the `count` checks at once, which can only be confirmed on an `interactive` terminal.
+fn confirm_deletions(count: usize, interactive: bool, input: impl BufRead, output: impl std::io::Write) -> Result<()> {
+	if !interactive {
+		anyhow::bail!("Refusing to delete {} checks without confirmation. [Use \"--assume-yes\" to delete them anyway]", count);
+	}
+	let question = format!("Delete these {} checks?", count);
+	// A failure to ask is taken as a "no".
+	if !confirm(&question, input, output).unwrap_or(false) {
+		anyhow::bail!("Nothing was deleted, as it wasn't confirmed");
+	}
 	Ok(())
 }
 
-fn describe(job: &mut CronJob) -> Option<(&str, &str, &mut Vec<Container>)> {
-	let CronJob {
-		spec,
-		metadata,
-		..
-	} = job;
+/// Asks `question` on `output`, and reads the answer from `input`: only "y" or "yes" agree.
+fn confirm(question: &str, mut input: impl BufRead, mut output: impl std::io::Write) -> std::io::Result<bool> {
+	write!(output, "{} [y/N] ", question)?;
+	output.flush()?;
+
+	let mut answer = String::new();
+	input.read_line(&mut answer)?;
+	let answer = answer.trim().to_ascii_lowercase();
+	Ok(answer == "y" || answer == "yes")
+}
+
+/// Splits a target into its context and namespaces, ignoring any overrides.
+fn split_target(target: &str) -> (&str, &str) {
+	let target = target.split(';').next().unwrap_or_default();
+	match target.split_once(':') {
+		Some(values) => values,
+		None => (target, "default"),
+	}
+}
+
+/// The fields every planned check starts from, before a job or its target's overrides change them.
+fn default_check(hc: &HealthChecksInfo, integrations: Option<String>) -> NewCheck {
+	NewCheck {
+		timeout: Some(hc.timeout),
+		grace: Some(hc.grace),
+		tz: Some(hc.timezone.clone()),
+		channels: integrations,
+		unique: (!hc.no_unique).then(|| unique_fields(&hc.unique_by)),
+		..Default::default()
+	}
+}
+
+fn unique_fields(keys: &[UniqueKey]) -> Vec<String> {
+	let mut keys = keys.to_vec();
+	keys.sort_unstable();
+	keys.dedup();
+	keys.into_iter()
+		.map(|key| key.field().into())
+		.collect()
+}
+
+/// Resolves the integrations to the ids of the channels they name (by id or name), along with those that match none.
+///
+/// The unknown ones are kept as given.
+fn resolve_integrations<'a>(integrations: &'a [String], channels: &[Channel]) -> (Vec<String>, Vec<&'a str>) {
+	let mut unknown = vec![];
+	let resolved = integrations.iter()
+		.map(|integration| match channels.iter().find(|channel| channel.id == *integration || channel.name == *integration) {
+			Some(channel) => channel.id.clone(),
+			None => {
+				unknown.push(integration.as_str());
+				integration.clone()
+			}
+		})
+		.collect();
+	(resolved, unknown)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::testing::{self, args, Server};
+
+	fn channel(id: &str, name: &str) -> Channel {
+		Channel {
+			id: id.into(),
+			name: name.into(),
+			kind: "email".into(),
+		}
+	}
+
+	#[test]
+	fn resolves_integrations_by_id_or_name() {
+		let channels = [channel("1111", "ops"), channel("2222", "billing")];
+		let integrations = ["ops".to_string(), "2222".to_string(), "unknown".to_string()];
+		let (resolved, unknown) = resolve_integrations(&integrations, &channels);
+		assert_eq!(resolved, ["1111", "2222", "unknown"]);
+		assert_eq!(unknown, ["unknown"]);
+	}
+
+	#[test]
+	fn generates_completions() {
+		let mut command = Args::command();
+		let mut script = vec![];
+		clap_complete::generate(Shell::Bash, &mut command, "healthkube", &mut script);
+		let script = String::from_utf8(script).unwrap();
+		assert!(!script.is_empty());
+		assert!(script.contains("healthkube"));
+	}
 
-	let name = metadata.name.as_deref()?;
+	#[test]
+	fn splits_targets() {
+		assert_eq!(split_target("prod:billing;timeout=300"), ("prod", "billing"));
+		assert_eq!(split_target("prod"), ("prod", "default"));
+	}
 
-	let spec = spec.as_mut()?;
-	let schedule = &*spec.schedule;
-	let spec = spec.job_template.spec.as_mut()?;
-	let spec = spec.template.spec.as_mut()?;
-	let containers = &mut spec.containers;
+	#[tokio::test]
+	async fn cuts_a_slow_run_off_at_the_deadline() {
+		testing::cluster();
+		let healthchecks = Server::start(|_| {
+			std::thread::sleep(Duration::from_secs(3));
+			(200, serde_json::json!({ "checks": [] }))
+		});
+		let args = args(&["--hc-key", "key", "--hc-url", &healthchecks.url, "--run-timeout", "1", "prod-a:billing"]);
+		let projects = Projects::new(&args).unwrap();
 
-	Some((name, schedule, containers))
+		let started = std::time::Instant::now();
+		let code = run_sync(&args, &projects, &NewCheck::default(), false).await.unwrap();
+		assert_eq!(code, 124);
+		assert!(started.elapsed() < Duration::from_secs(2), "{:?}", started.elapsed());
+	}
+
+	#[tokio::test]
+	async fn refuses_more_than_the_max_checks_before_any_write() {
+		testing::cluster();
+		let healthchecks = Server::start(|_| (200, serde_json::json!({ "checks": [] })));
+		let args = args(&["--hc-key", "key", "--hc-url", &healthchecks.url, "--max-checks", "0", "prod-a:billing"]);
+		let projects = Projects::new(&args).unwrap();
+
+		let err = run_sync(&args, &projects, &NewCheck::default(), false).await.unwrap_err();
+		assert!(err.to_string().starts_with("The synchronisation would create 1 checks, more than the allowed 0."), "{}", err);
+		let requests: Vec<_> = healthchecks.requests()
+			.into_iter()
+			.map(|request| (request.method, request.path))
+			.collect();
+		assert_eq!(requests, [("GET".to_string(), "/checks".to_string())]);
+	}
+
+	#[tokio::test]
+	async fn plans_offline_without_any_network() {
+		testing::cluster();
+		let healthchecks = Server::start(|_| (500, serde_json::json!({})));
+		let fixture = testing::fixture(&[testing::cron_job("nightly-backup-job", "0 2 * * *")]);
+		let fixture = fixture.to_str().unwrap();
+		// The "dev" cluster can't be reached, and the run would fail if HealthChecks were called.
+		let args = args(&["--hc-key", "key", "--hc-url", &healthchecks.url, "--dry-run", "--detect-changes", "--offline", fixture, "dev:billing"]);
+		let projects = Projects::new(&args).unwrap();
+
+		let code = run_sync(&args, &projects, &NewCheck::default(), false).await.unwrap();
+		assert_eq!(code, DETECT_CHANGES_EXIT_CODE, "the job should be planned");
+		assert!(healthchecks.requests().is_empty());
+	}
+	fn channels(status: u16) -> Server {
+		Server::start(move |_| (status, serde_json::json!({
+			"channels": [
+				{ "id": "1111", "name": "ops", "kind": "email" },
+				{ "id": "2222", "name": "billing", "kind": "slack" },
+			],
+		})))
+	}
+
+	async fn integrations(healthchecks: &Server, flags: &[&str]) -> Result<Option<String>> {
+		let args = args(&[&["--hc-key", "key", "--hc-url", healthchecks.url.as_str()], flags, &["prod-a"]].concat());
+		default_integrations(&args, &Projects::new(&args)?).await
+	}
+
+	#[tokio::test]
+	async fn tells_the_default_integrations_from_none() {
+		let healthchecks = channels(200);
+		assert_eq!(integrations(&healthchecks, &["--integrations", "ops", "--integrations", "billing"]).await.unwrap().as_deref(), Some("1111,2222"));
+		assert_eq!(integrations(&healthchecks, &[]).await.unwrap(), None);
+		assert_eq!(integrations(&healthchecks, &["--no-integrations"]).await.unwrap().as_deref(), Some(""));
+	}
+
+	#[test]
+	fn unique_fields_are_sorted_and_deduplicated() {
+		assert_eq!(unique_fields(&[UniqueKey::Tags, UniqueKey::Name, UniqueKey::Tags]), ["name", "tags"]);
+		assert!(unique_fields(&[]).is_empty());
+	}
+
+	#[tokio::test]
+	async fn a_dry_run_lists_the_checks_to_clear_and_deletes_nothing() {
+		testing::cluster();
+		let healthchecks = testing::healthchecks(vec![testing::check("f6a2", "old-backup-job", ""), testing::check("c4d1", "old-report-job", "")]);
+		let args = args(&["--hc-key", "key", "--hc-url", &healthchecks.url, "--dry-run", "--output", "json", "--clear-existing-checks", "--detect-changes", "prod-a:billing"]);
+		let projects = Projects::new(&args).unwrap();
+
+		let code = run_sync(&args, &projects, &NewCheck::default(), false).await.unwrap();
+		assert_eq!(code, DETECT_CHANGES_EXIT_CODE);
+		let calls: Vec<_> = healthchecks.requests()
+			.into_iter()
+			.map(|request| format!("{} {}", request.method, request.path))
+			.collect();
+		assert_eq!(calls, ["GET /checks"]);
+	}
+
+	#[test]
+	fn reads_targets_in_place_of_the_dash() {
+		let mut targets = vec!["a".to_string(), "-".to_string(), "d".to_string()];
+		read_stdin_targets(&mut targets, "b\n\n# comment\n  c  \n".as_bytes()).unwrap();
+		assert_eq!(targets, ["a", "b", "c", "d"]);
+
+		let mut targets = vec!["a".to_string()];
+		read_stdin_targets(&mut targets, "b\n".as_bytes()).unwrap();
+		assert_eq!(targets, ["a"]);
+	}
+
+	#[test]
+	fn needs_a_target_from_stdin() {
+		let mut targets = vec!["-".to_string()];
+		assert!(read_stdin_targets(&mut targets, "# nothing\n".as_bytes()).is_err());
+	}
+
+	#[tokio::test]
+	async fn lists_the_channels_with_the_project_token() {
+		let healthchecks = channels(200);
+		integrations(&healthchecks, &["--hc-project-token", "token", "--integrations", "ops"]).await.unwrap();
+
+		let requests = healthchecks.requests();
+		assert_eq!(requests.len(), 1);
+		assert_eq!(requests[0].path, "/channels");
+		assert_eq!(requests[0].header("x-api-key"), Some("token"));
+	}
+
+	#[tokio::test]
+	async fn signals_pending_changes_with_the_exit_code() {
+		testing::cluster();
+		let code = |jobs: Vec<k8s_openapi::api::batch::v1::CronJob>| async move {
+			let fixture = testing::fixture(&jobs);
+			let args = args(&["--dry-run", "--detect-changes", "--offline", fixture.to_str().unwrap(), "prod-a:billing"]);
+			run_sync(&args, &Projects::new(&args).unwrap(), &NewCheck::default(), false).await.unwrap()
+		};
+
+		assert_eq!(code(vec![testing::cron_job("nightly-backup-job", "0 2 * * *")]).await, 2);
+		assert_eq!(code(vec![]).await, 0);
+	}
+
+	#[test]
+	fn the_dumped_config_redacts_the_secrets() {
+		let args = args(&["--hc-key", "hc-s3cr3t", "--hc-context-key", "prod-b=context-s3cr3t", "--dry-run", "--max-checks", "10", "prod-a"]);
+		let dumped = serde_json::to_value(&args).unwrap();
+
+		assert_eq!(dumped["dry-run"], true);
+		assert_eq!(dumped["max-checks"], 10);
+		assert_eq!(dumped["targets"], serde_json::json!(["prod-a"]));
+		assert_eq!(dumped["hc"]["key"], "***");
+		assert_eq!(dumped["hc"]["context-projects"], serde_json::json!([{ "context": "prod-b", "key": "***", "url": null }]));
+		assert!(!dumped.to_string().contains("s3cr3t"));
+	}
+
+	#[test]
+	fn parses_durations() {
+		assert_eq!(parse_duration("90"), Ok(90));
+		assert_eq!(parse_duration("90s"), Ok(90));
+		assert_eq!(parse_duration("30m"), Ok(30 * 60));
+		assert_eq!(parse_duration("12h"), Ok(12 * 60 * 60));
+		assert_eq!(parse_duration("7d"), Ok(7 * 24 * 60 * 60));
+		assert!(parse_duration("5w").is_err());
+		assert!(parse_duration("m").is_err());
+		assert!(parse_duration("99999999999999999999d").is_err());
+	}
+
+	#[test]
+	fn parses_context_projects() {
+		let project = parse_context_project("prod:billing=key@https://hc.example.com").unwrap();
+		assert_eq!(project.context, "prod:billing");
+		assert_eq!(project.key, "key");
+		assert_eq!(project.url.as_deref(), Some("https://hc.example.com"));
+
+		let project = parse_context_project("prod=key").unwrap();
+		assert_eq!((project.context.as_str(), project.url), ("prod", None));
+
+		for value in ["prod", "=key", "prod=", "prod=@https://hc.example.com"] {
+			assert!(parse_context_project(value).is_err(), "{}", value);
+		}
+	}
+
+	/// The key each of the `plans` is sent to HealthChecks with.
+	fn keys_used(flags: &[&str], plans: &[plan::NamespacePlan]) -> Vec<String> {
+		let healthchecks = testing::healthchecks(vec![]);
+		let args = args(&[&["--hc-key", "global-key", "--hc-url", healthchecks.url.as_str()], flags, &["prod"]].concat());
+		let projects = Projects::new(&args).unwrap();
+		for plan in plans {
+			projects.client(plan).unwrap().get_checks().unwrap();
+		}
+
+		healthchecks.requests()
+			.iter()
+			.map(|request| request.header("x-api-key").unwrap_or_default().to_string())
+			.collect()
+	}
+
+	#[test]
+	fn a_mapped_context_uses_its_own_key() {
+		let plan = |context: &str| plan::NamespacePlan {
+			context: context.into(),
+			..testing::namespace_plan()
+		};
+		let keys = keys_used(&["--hc-context-key", "prod=prod-key"], &[plan("prod"), plan("staging")]);
+		assert_eq!(keys, ["prod-key", "global-key"]);
+	}
+
+	#[test]
+	fn sends_the_key_of_the_most_specific_mapping() {
+		let plan = |namespace: &str| plan::NamespacePlan {
+			namespace: namespace.into(),
+			..testing::namespace_plan()
+		};
+		let flags = ["--hc-context-key", "prod=prod-key,prod:billing=billing-key"];
+		assert_eq!(keys_used(&flags, &[plan("billing"), plan("infra")]), ["billing-key", "prod-key"]);
+	}
+
+	#[test]
+	fn only_yes_confirms() {
+		for (answer, expected) in [("y\n", true), (" YES \n", true), ("n\n", false), ("\n", false), ("", false), ("yep\n", false)] {
+			let mut output = vec![];
+			assert_eq!(confirm("Go?", answer.as_bytes(), &mut output).unwrap(), expected, "{:?}", answer);
+			assert_eq!(output, b"Go? [y/N] ");
+		}
+	}
+
+	#[test]
+	fn resolves_a_name_collision_by_the_policy() {
+		let kept = |policy: &str| -> Result<Vec<String>> {
+			let args = args(&["--hc-key", "key", "--hc-url", "http://127.0.0.1:1", "--on-name-collision", policy, "prod"]);
+			let mut plans: Vec<_> = ["billing", "infra"].into_iter()
+				.map(|namespace| plan::NamespacePlan {
+					namespace: namespace.into(),
+					jobs: vec![testing::planned_job("nightly-backup-job")],
+					..testing::namespace_plan()
+				})
+				.collect();
+			resolve_name_collisions(&args, &Projects::new(&args)?, &mut plans)?;
+
+			for plan in &plans {
+				let skipped = plan.jobs.is_empty().then(|| ("nightly-backup-job".to_string(), plan::SkipReason::NameCollision));
+				assert_eq!(plan.skipped, Vec::from_iter(skipped));
+			}
+			Ok(plans.into_iter().filter(|plan| !plan.jobs.is_empty()).map(|plan| plan.namespace).collect())
+		};
+
+		assert!(kept("error").is_err());
+		assert_eq!(kept("warn").unwrap(), ["infra"]);
+		assert_eq!(kept("first").unwrap(), ["billing"]);
+		assert_eq!(kept("last").unwrap(), ["infra"]);
+	}
+
+	#[test]
+	fn refuses_to_delete_without_a_terminal() {
+		let err = confirm_deletions(2, false, "y\n".as_bytes(), std::io::sink()).unwrap_err();
+		assert!(err.to_string().starts_with("Refusing to delete 2 checks"), "{}", err);
+
+		assert!(confirm_deletions(2, true, "n\n".as_bytes(), std::io::sink()).is_err());
+		assert!(confirm_deletions(2, true, "y\n".as_bytes(), std::io::sink()).is_ok());
+	}
+
+	#[tokio::test]
+	async fn reads_the_credentials_from_the_secret() {
+		let cluster = Server::start(|request| {
+			let data = match request.path.as_str() {
+				"/api/v1/namespaces/ops/secrets/healthchecks" => serde_json::json!({ "key": "aGMta2V5", "url": "aHR0cHM6Ly9oYy5leGFtcGxlLmNvbS9hcGkvdjMv" }),
+				_ => serde_json::json!({ "key": "aGMta2V5" }),
+			};
+			(200, serde_json::json!({ "apiVersion": "v1", "kind": "Secret", "metadata": {}, "data": data }))
+		});
+
+		let credentials = read_credentials(cluster.kube_client(), "ops", "healthchecks").await.unwrap();
+		assert_eq!(credentials, ("hc-key".to_string(), "https://hc.example.com/api/v3/".to_string()));
+
+		let err = read_credentials(cluster.kube_client(), "ops", "partial").await.unwrap_err();
+		assert_eq!(err.to_string(), "The secret \"ops/partial\" has no \"url\" field");
+	}
+
+	#[test]
+	fn pretty_json_has_the_same_structure() {
+		let mut summary = Summary::new(false);
+		summary.record(Outcome {
+			context: Some("prod".into()),
+			namespace: Some("billing".into()),
+			name: "nightly-backup-job".into(),
+			check: Some("f6a2".into()),
+			action: Action::Created,
+		});
+		let document = report::ResultDocument::new(&summary);
+
+		let compact = to_json(&args(&["prod"]), &document).unwrap();
+		let pretty = to_json(&args(&["--json-pretty", "prod"]), &document).unwrap();
+		assert_eq!(compact.lines().count(), 1);
+		assert!(pretty.lines().count() > 1);
+		assert_eq!(serde_json::from_str::<serde_json::Value>(&compact).unwrap(), serde_json::from_str::<serde_json::Value>(&pretty).unwrap());
+	}
+
+	#[tokio::test]
+	async fn an_apply_cant_remove_the_env() {
+		for flags in [&["--remove-env", "OLD_HC_ID"][..], &["--remove-managed-env", "--env-key", "HC_ID"]] {
+			let args = args(&[flags, &["--replace-strategy", "apply", "prod"]].concat());
+			let err = run(&args).await.unwrap_err();
+			assert!(err.to_string().contains("can't be used with \"--replace-strategy apply\""), "{}", err);
+		}
+	}
+
+	#[tokio::test]
+	async fn explicit_ids_dont_need_the_channels() {
+		assert_eq!(integrations(&channels(500), &["--integrations", "1111"]).await.unwrap().as_deref(), Some("1111"));
+	}
+
+	#[test]
+	fn no_unique_leaves_out_the_unique_fields() {
+		assert_eq!(default_check(&args(&["prod"]).hc, None).unique, Some(vec!["name".to_string()]));
+		assert_eq!(default_check(&args(&["--no-unique", "prod"]).hc, None).unique, None);
+	}
+
+	#[tokio::test]
+	async fn merges_the_integrations_file_with_the_flags() {
+		let path = std::env::temp_dir().join(format!("healthkube-test-{}.integrations", std::process::id()));
+		std::fs::write(&path, "# The billing team's channel\n\n  billing  \n").unwrap();
+		let healthchecks = channels(200);
+		let mut args = args(&["--hc-key", "key", "--hc-url", &healthchecks.url, "--integrations", "1111", "--integrations-file", path.to_str().unwrap(), "prod-a"]);
+
+		read_integrations_file(&mut args.hc).unwrap();
+		assert_eq!(args.hc.integrations, ["1111", "billing"]);
+		assert_eq!(default_integrations(&args, &Projects::new(&args).unwrap()).await.unwrap().as_deref(), Some("1111,2222"));
+	}
+
+	#[test]
+	fn strict_fails_once_anything_warned() {
+		// The warnings are counted across the whole run, so only the failure can be told apart.
+		warning!("a warning for --strict");
+		assert!(check_strict(&args(&["--strict", "prod"])).is_err());
+		assert!(check_strict(&args(&["prod"])).is_ok());
+	}
 }
@@ -0,0 +1,95 @@
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Where --output-file copies the text output to.
+static FILE: Mutex<Option<File>> = Mutex::new(None);
+
+/// How many warnings were printed, for --strict.
+static WARNINGS: AtomicUsize = AtomicUsize::new(0);
+
+/// Prints a line of the text output, like println!, and copies it to the --output-file.
+macro_rules! say {
+	($($arg:tt)*) => {
+		$crate::output::line(&format!($($arg)*))
+	};
+}
+pub(crate) use say;
+
+/// Prints a "Warning: ..." line to stderr, like eprintln!, and counts it for --strict.
+macro_rules! warning {
+	($($arg:tt)*) => {
+		$crate::output::print_warning(&format!($($arg)*))
+	};
+}
+pub(crate) use warning;
+
+/// Copies every following line of text output to `path` (without any colors).
+pub fn tee(path: &Path) -> std::io::Result<()> {
+	let file = File::create(path)?;
+	if let Ok(mut tee) = FILE.lock() {
+		*tee = Some(file);
+	}
+	Ok(())
+}
+
+pub fn line(line: &str) {
+	println!("{}", line);
+
+	let Ok(mut tee) = FILE.lock() else {
+		return;
+	};
+	if let Some(file) = tee.as_mut() {
+		// Losing the copy shouldn't stop the synchronisation.
+		let _ = writeln!(file, "{}", strip_ansi(line));
+	}
+}
+
+pub fn print_warning(message: &str) {
+	WARNINGS.fetch_add(1, Ordering::Relaxed);
+	eprintln!("Warning: {}", message);
+}
+
+/// How many warnings have been printed so far.
+pub fn warnings() -> usize {
+	WARNINGS.load(Ordering::Relaxed)
+}
+
+/// Removes the color codes (i.e. "ESC [ ... m").
+fn strip_ansi(line: &str) -> String {
+	let mut stripped = String::with_capacity(line.len());
+	let mut chars = line.chars();
+	while let Some(c) = chars.next() {
+		if c == '\u{1b}' {
+			chars.by_ref().find(|c| *c == 'm');
+			continue;
+		}
+		stripped.push(c);
+	}
+	stripped
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::style::Status;
+
+	#[test]
+	fn strips_the_colors() {
+		assert_eq!(strip_ansi(&format!("-> {}(\"f6a2\")", Status::Created.paint(true))), "-> Created(\"f6a2\")");
+		assert_eq!(strip_ansi("plain"), "plain");
+	}
+
+	#[test]
+	fn copies_the_lines_without_colors() {
+		// The copy is shared by the whole run (so every other test's lines end up in it too).
+		let path = std::env::temp_dir().join(format!("healthkube-test-{}.log", std::process::id()));
+		tee(&path).unwrap();
+		line(&format!("\t\t: {: <50} -> {}(\"f6a2\")", "nightly-backup-job", Status::Created.paint(true)));
+
+		let copied = std::fs::read_to_string(&path).unwrap();
+		assert!(copied.contains(&format!("\t\t: {: <50} -> Created(\"f6a2\")\n", "nightly-backup-job")), "{}", copied);
+	}
+}
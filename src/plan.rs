@@ -0,0 +1,1201 @@
+use std::collections::{BTreeSet, HashMap};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+use healthchecks::model::{Check, NewCheck};
+use k8s_openapi::api::batch::v1::CronJob;
+use k8s_openapi::api::core::v1::{Container, Namespace};
+use k8s_openapi::chrono::{self, DateTime, Utc};
+use kube::{Client, Config};
+use regex::Regex;
+use kube::api::ListParams;
+use kube::config::{Kubeconfig, KubeConfigOptions};
+
+use crate::Args;
+use crate::error::SyncError;
+use crate::output::warning;
+use crate::schedule::{self, ScheduleError};
+use crate::target::Overrides;
+
+/// Overrides the schedule that's sent to HealthChecks, when the CronJob's own schedule doesn't reflect the real cadence.
+/// It's also what gives a schedule-less CronJob its check.
+pub const SCHEDULE_ANNOTATION: &str = "healthkube.io/schedule";
+
+/// Overrides the integrations/channels of a single check, as comma-separated ids or names.
+pub const CHANNELS_ANNOTATION: &str = "healthkube.io/channels";
+
+/// Everything that will be synchronised for a single namespace.
+///
+/// Building one only reads from the cluster, so they can all be inspected before anything is changed.
+pub struct NamespacePlan {
+	pub context: String,
+	pub namespace: String,
+	/// None, when planned --offline.
+	pub api: Option<kube::Api<CronJob>>,
+	/// How many CronJobs were listed, before any were filtered out.
+	pub listed: usize,
+	/// Whether the namespace is being deleted (it has a deletionTimestamp), so its checks are paused instead.
+	pub terminating: bool,
+	pub jobs: Vec<PlannedJob>,
+	pub skipped: Vec<(String, SkipReason)>,
+	/// How often each segment occurs, sorted by count (descending).
+	pub frequencies: Vec<(String, u8)>,
+}
+
+/// A CronJob, and the check that it should have.
+pub struct PlannedJob {
+	pub job: CronJob,
+	pub check: NewCheck,
+}
+
+impl PlannedJob {
+	pub fn name(&self) -> &str {
+		self.check.name.as_deref().unwrap_or_default()
+	}
+
+	/// The integrations/channels that the job's annotation asks for, if it has one.
+	pub fn channels(&self) -> Option<Vec<&str>> {
+		let channels = annotation(&self.job, CHANNELS_ANNOTATION)?;
+		let channels = channels.split(',')
+			.map(str::trim)
+			.filter(|channel| !channel.is_empty())
+			.collect();
+		Some(channels)
+	}
+}
+
+/// Why a CronJob couldn't be synchronised.
+#[derive(thiserror::Error, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum SkipReason {
+	#[error("no name")]
+	Unnamed,
+	#[error("no spec")]
+	NoSpec,
+	#[error("no jobTemplate")]
+	NoJobTemplate,
+	#[error("no pod spec")]
+	NoPodSpec,
+	#[error("invalid schedule")]
+	InvalidSchedule,
+	#[error("empty schedule")]
+	EmptySchedule,
+	#[error("not modified recently")]
+	NotModified,
+	#[error("declined")]
+	Declined,
+	#[error("name collision")]
+	NameCollision,
+	#[error("check limit reached")]
+	CheckLimit,
+}
+
+pub async fn plan(args: &Args, targets: &[String], default_check: &NewCheck) -> Result<Vec<NamespacePlan>, SyncError> {
+	let kubeconfig = Kubeconfig::read().map_err(SyncError::Kubeconfig)?;
+
+	// Ensure every target names a known context, before anything talks to a cluster.
+	let available: Vec<_> = kubeconfig.contexts.iter()
+		.map(|named| named.name.clone())
+		.collect();
+	let mut expanded = vec![];
+	for target in targets {
+		let matching = expand_context(target, &available).map_err(|source| SyncError::Pattern {
+			target: target.clone(),
+			source,
+		})?;
+		expanded.extend(matching);
+	}
+	let targets = expanded;
+	let mut missing: Vec<_> = targets.iter()
+		.map(|target| crate::split_target(target).0)
+		.filter(|context| !available.iter().any(|name| name == context))
+		.map(String::from)
+		.collect();
+	missing.sort_unstable();
+	missing.dedup();
+	if !missing.is_empty() {
+		return Err(SyncError::UnknownContexts { missing, available });
+	}
+
+	let mut overrides = vec![];
+	let mut patterns = vec![];
+	for target in &targets {
+		let parsed = Overrides::parse(target).map_err(|source| SyncError::Target {
+			target: target.clone(),
+			source,
+		})?;
+		overrides.push(parsed);
+
+		let (_, namespaces) = crate::split_target(target);
+		let pattern = namespace_pattern(namespaces).map_err(|source| SyncError::Pattern {
+			target: target.clone(),
+			source,
+		})?;
+		patterns.push(pattern);
+	}
+
+	if let Some(path) = &args.offline {
+		let fixture = std::fs::read(path).map_err(|source| SyncError::Fixture {
+			path: path.clone(),
+			source,
+		})?;
+		let fixture: Fixture = serde_json::from_slice(&fixture).map_err(|source| SyncError::FixtureFormat {
+			path: path.clone(),
+			source,
+		})?;
+		let jobs = match fixture {
+			Fixture::List { items } => items,
+			Fixture::Jobs(jobs) => jobs,
+		};
+		return Ok(plan_offline(args, default_check, &targets, &overrides, &patterns, &jobs));
+	}
+
+	let mut opts = KubeConfigOptions::default();
+
+	let mut plans = vec![];
+
+	for ((target, overrides), pattern) in targets.iter().zip(&overrides).zip(&patterns) {
+		let (context, namespaces) = crate::split_target(target);
+		let default_check = &overrides.apply(default_check);
+		opts.context = Some(context.into());
+
+		let config = Config::from_custom_kubeconfig(kubeconfig.clone(), &opts)
+			.await
+			.map_err(|source| SyncError::Context {
+				context: context.into(),
+				source,
+			})?;
+
+		let kube_client = Client::try_from(config.clone()).map_err(|source| SyncError::Client {
+			context: context.into(),
+			source,
+		})?;
+
+		for (namespace, terminating) in resolve_namespaces(args, context, &kube_client, namespaces, pattern.as_ref()).await? {
+			let namespace = &*namespace;
+			let api: kube::Api<CronJob> = kube::Api::namespaced(kube_client.clone(), namespace);
+			let started = Instant::now();
+			let jobs = api.list(&list_params(args)).await;
+			let target = format!("{}:/apis/batch/v1/namespaces/{}/cronjobs", context, namespace);
+			crate::trace::log(args, "GET", "cronjobs", &target, started, &jobs);
+			let jobs = jobs
+				.map_err(|source| SyncError::List {
+					context: context.into(),
+					namespace: namespace.into(),
+					source,
+				})?
+				.items;
+
+			let mut plan = NamespacePlan {
+				context: context.into(),
+				namespace: namespace.into(),
+				api: Some(api),
+				listed: 0,
+				terminating,
+				jobs: vec![],
+				skipped: vec![],
+				frequencies: vec![],
+			};
+			plan_jobs(args, default_check, jobs, &mut plan);
+			plans.push(plan);
+		}
+	}
+
+	Ok(plans)
+}
+
+/// The CronJobs used by --offline, either as a list object (like kubectl's output) or a plain array.
+#[derive(serde::Deserialize)]
+#[serde(untagged)]
+enum Fixture {
+	List { items: Vec<CronJob> },
+	Jobs(Vec<CronJob>),
+}
+
+/// Plans against the fixture's CronJobs, as though they were listed from each target.
+fn plan_offline(
+	args: &Args,
+	default_check: &NewCheck,
+	targets: &[String],
+	overrides: &[Overrides],
+	patterns: &[Option<Regex>],
+	jobs: &[CronJob],
+) -> Vec<NamespacePlan> {
+	if args.label_selector.is_some() || args.field_selector.is_some() {
+		warning!("The selectors aren't applied to the fixture");
+	}
+
+	let namespace_of = |job: &CronJob| job.metadata.namespace.clone().unwrap_or_else(|| "default".into());
+
+	let mut plans = vec![];
+
+	for ((target, overrides), pattern) in targets.iter().zip(overrides).zip(patterns) {
+		let (context, namespaces) = crate::split_target(target);
+		let default_check = &overrides.apply(default_check);
+
+		let namespaces: Vec<String> = if let Some(pattern) = pattern {
+			let mut namespaces: Vec<_> = jobs.iter()
+				.map(namespace_of)
+				.filter(|namespace| pattern.is_match(namespace) && !is_excluded(args, namespace))
+				.collect();
+			namespaces.sort_unstable();
+			namespaces.dedup();
+			namespaces
+		} else {
+			namespaces.split(',').map(String::from).collect()
+		};
+
+		for namespace in namespaces {
+			let jobs = jobs.iter()
+				.filter(|job| namespace_of(job) == namespace)
+				.cloned()
+				.collect();
+
+			let mut plan = NamespacePlan {
+				context: context.into(),
+				namespace,
+				api: None,
+				listed: 0,
+				terminating: false,
+				jobs: vec![],
+				skipped: vec![],
+				frequencies: vec![],
+			};
+			plan_jobs(args, default_check, jobs, &mut plan);
+			plans.push(plan);
+		}
+	}
+
+	plans
+}
+
+/// Whether the namespace part of a target is a pattern, rather than a list of namespaces.
+pub fn is_pattern(namespaces: &str) -> bool {
+	namespaces.starts_with('~') || namespaces.contains('*')
+}
+
+/// Expands a target whose context is a glob (e.g. "prod-*:billing") or a regex (e.g. "~^prod-:billing"), into a target per matching context.
+///
+/// A pattern that matches none is kept as it is, so it's reported as an unknown context.
+pub fn expand_context(target: &str, available: &[String]) -> Result<Vec<String>, regex::Error> {
+	let (context, _) = crate::split_target(target);
+	let Some(pattern) = namespace_pattern(context)? else {
+		return Ok(vec![target.into()]);
+	};
+
+	// The context is always at the front, followed by the namespaces and overrides.
+	let rest = &target[context.len()..];
+	let expanded: Vec<_> = available.iter()
+		.filter(|name| pattern.is_match(name))
+		.map(|name| format!("{}{}", name, rest))
+		.collect();
+	if expanded.is_empty() {
+		return Ok(vec![target.into()]);
+	}
+	Ok(expanded)
+}
+
+/// Compiles the namespace (or context) part of a target, if it's a glob (e.g. "team-*", or just "*") or a regex (e.g. "~^team-").
+fn namespace_pattern(namespaces: &str) -> Result<Option<Regex>, regex::Error> {
+	if let Some(regex) = namespaces.strip_prefix('~') {
+		return Regex::new(regex).map(Some);
+	}
+	if !is_pattern(namespaces) {
+		return Ok(None);
+	}
+
+	let glob = namespaces.split('*')
+		.map(regex::escape)
+		.collect::<Vec<_>>()
+		.join(".*");
+	Regex::new(&format!("^{}$", glob)).map(Some)
+}
+
+/// Expands the namespace part of a target, which is either a list of namespaces or a pattern.
+///
+/// Along with each namespace, it tells whether it's being deleted.
+async fn resolve_namespaces(
+	args: &Args,
+	context: &str,
+	client: &Client,
+	namespaces: &str,
+	pattern: Option<&Regex>,
+) -> Result<Vec<(String, bool)>, SyncError> {
+	let Some(pattern) = pattern else {
+		let mut resolved = vec![];
+		for namespace in namespaces.split(',') {
+			resolved.push((namespace.to_string(), is_terminating(args, context, client, namespace).await));
+		}
+		return Ok(resolved);
+	};
+
+	let namespaces = list_namespaces(args, context, client).await?;
+
+	let mut namespaces: Vec<_> = namespaces
+		.into_iter()
+		.filter(|(namespace, _)| pattern.is_match(namespace) && !is_excluded(args, namespace))
+		.collect();
+	namespaces.sort_unstable();
+
+	Ok(namespaces)
+}
+
+/// Whether the namespace has a deletionTimestamp.
+///
+/// It's only a hint, so when the namespace can't be read (e.g. without the RBAC to do so), it's taken as not.
+async fn is_terminating(args: &Args, context: &str, client: &Client, namespace: &str) -> bool {
+	let api: kube::Api<Namespace> = kube::Api::all(client.clone());
+	let started = Instant::now();
+	let found = api.get(namespace).await;
+	let target = format!("{}:/api/v1/namespaces/{}", context, namespace);
+	crate::trace::log(args, "GET", "namespaces", &target, started, &found);
+	found.is_ok_and(|found| found.metadata.deletion_timestamp.is_some())
+}
+
+/// How many namespaces are listed per page.
+const NAMESPACE_PAGE_SIZE: u32 = 500;
+
+/// How often a page is attempted, before giving up.
+const PAGE_ATTEMPTS: u64 = 3;
+
+/// How many retries were made across the whole run, for --retry-budget.
+static RETRIES: AtomicUsize = AtomicUsize::new(0);
+
+/// Takes a retry out of the --retry-budget, if there's any left.
+fn take_retry(args: &Args) -> bool {
+	let Some(budget) = args.retry_budget else {
+		return true;
+	};
+	RETRIES.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |used| (used < budget).then_some(used + 1)).is_ok()
+}
+
+/// Lists the names of every namespace, and whether it's being deleted, page by page.
+///
+/// A page that fails transiently is retried with the same continue token, so the listing picks up where it left off.
+async fn list_namespaces(args: &Args, context: &str, client: &Client) -> Result<Vec<(String, bool)>, SyncError> {
+	let api: kube::Api<Namespace> = kube::Api::all(client.clone());
+	let target = format!("{}:/api/v1/namespaces", context);
+
+	let mut namespaces = vec![];
+	let mut continue_token = None;
+
+	loop {
+		let mut params = ListParams::default().limit(NAMESPACE_PAGE_SIZE);
+		params.label_selector = args.namespace_selector.clone();
+		params.continue_token = continue_token;
+
+		let mut attempt = 1;
+		let page = loop {
+			let started = Instant::now();
+			let page = api.list(&params).await;
+			crate::trace::log(args, "GET", "namespaces", &target, started, &page);
+
+			match page {
+				Ok(page) => break page,
+				Err(err) if attempt < PAGE_ATTEMPTS && is_transient(&err) && take_retry(args) => {
+					warning!("Unable to list the namespaces in \"{}\", retrying: {}", context, err);
+					tokio::time::sleep(Duration::from_millis(500 * attempt)).await;
+					attempt += 1;
+				}
+				Err(source) => {
+					return Err(SyncError::Namespaces {
+						context: context.into(),
+						source,
+					});
+				}
+			}
+		};
+
+		namespaces.extend(page.items.into_iter().filter_map(|namespace| {
+			let terminating = namespace.metadata.deletion_timestamp.is_some();
+			Some((namespace.metadata.name?, terminating))
+		}));
+
+		continue_token = page.metadata.continue_.filter(|token| !token.is_empty());
+		if continue_token.is_none() {
+			return Ok(namespaces);
+		}
+	}
+}
+
+/// Server errors, throttling and connection failures are worth retrying, anything else won't change.
+fn is_transient(err: &kube::Error) -> bool {
+	match err {
+		kube::Error::Api(response) => response.code >= 500 || response.code == 429,
+		_ => true,
+	}
+}
+
+fn is_excluded(args: &Args, namespace: &str) -> bool {
+	if args.exclude_namespaces.iter().any(|excluded| excluded == namespace) {
+		return true;
+	}
+	match &args.exclude_namespace_regex {
+		Some(regex) => regex.is_match(namespace),
+		None => false,
+	}
+}
+
+fn list_params(args: &Args) -> ListParams {
+	let mut params = ListParams::default();
+	if let Some(selector) = &args.label_selector {
+		params = params.labels(selector);
+	}
+	if let Some(selector) = &args.field_selector {
+		params = params.fields(selector);
+	}
+	params
+}
+
+fn plan_jobs(args: &Args, default_check: &NewCheck, mut jobs: Vec<CronJob>, plan: &mut NamespacePlan) {
+	plan.listed = jobs.len();
+
+	// The API doesn't guarantee a stable order, so sort to keep the output comparable between runs.
+	jobs.sort_by(|a, b| {
+		let a = (&a.metadata.name, &a.metadata.namespace);
+		let b = (&b.metadata.name, &b.metadata.namespace);
+		a.cmp(&b)
+	});
+
+	let mut definitions = vec![];
+
+	for mut job in jobs {
+		let schedule_override = annotated_schedule(&job);
+
+		match describe(&mut job) {
+			Ok((name, schedule, containers)) => {
+				let name = name.to_string();
+				// Only looked up when it's read, so a job without the --primary-container isn't warned about needlessly.
+				let primary = (args.tag_from_image || args.tz_from_container_env)
+					.then(|| primary_container(args, &name, containers))
+					.flatten();
+				let image = primary
+					.and_then(|container| container.image.as_deref())
+					.and_then(image_repository)
+					.map(str::to_string);
+				let tz = match primary.and_then(container_timezone).filter(|_| args.tz_from_container_env) {
+					Some(tz) if is_known_timezone(tz) => Some(tz.to_string()),
+					Some(tz) => {
+						warning!("\"{}\" has an unknown timezone in its container's env: {}", name, tz);
+						None
+					}
+					None => None,
+				};
+				let schedule = match schedule_override {
+					Err(reason) => {
+						let err = SyncError::InvalidSchedule {
+							job: name.clone(),
+							reason,
+						};
+						warning!("{}", err);
+						plan.skipped.push((name, SkipReason::InvalidSchedule));
+						continue;
+					}
+					Ok(Some(schedule)) => schedule,
+					Ok(None) if schedule.trim().is_empty() => {
+						warning!(
+							"\"{}\" has an empty schedule, so it's skipped [Use the \"{}\" annotation to give it one]",
+							name,
+							SCHEDULE_ANNOTATION,
+						);
+						plan.skipped.push((name, SkipReason::EmptySchedule));
+						continue;
+					}
+					Ok(None) => schedule.to_string(),
+				};
+				definitions.push((job, name, schedule, image, tz));
+			}
+			Err(reason) => {
+				let name = job.metadata.name.unwrap_or_default();
+				warning!("Skipping \"{}\": {}", name, reason);
+				plan.skipped.push((name, reason));
+			}
+		}
+	}
+
+	let tags: Vec<_> = {
+		let mut common_tags: HashMap<&str, u8> = definitions.iter()
+			.flat_map(|(_, name, _, _, _)| name.split('-'))
+			.fold(HashMap::new(), |mut acc, item| {
+				*acc.entry(item).or_default() += 1;
+				acc
+			});
+
+		common_tags.remove("job");
+		if !args.tag_allowlist.is_empty() {
+			common_tags.retain(|segment, _| args.tag_allowlist.iter().any(|word| word == segment));
+		}
+		common_tags.retain(|segment, _| !args.tag_denylist.iter().any(|word| word == segment));
+
+		plan.frequencies = tag_histogram(&common_tags);
+
+		if args.rank > 0 {
+			common_tags.retain(|_, v| *v >= args.rank);
+		}
+
+		definitions.iter()
+			.map(|(job, name, _, image, _)| {
+				let mut tags: Vec<_> = name.split('-')
+					.filter(|segment| common_tags.contains_key(*segment))
+					.map(String::from)
+					.collect();
+				if let Some(max_tags) = args.max_tags.filter(|max_tags| tags.len() > *max_tags) {
+					// Keeps the most frequent segments, in the order they appear in the name.
+					let mut ranked = tags.clone();
+					ranked.sort_by_key(|tag| std::cmp::Reverse(common_tags[tag.as_str()]));
+					ranked.truncate(max_tags);
+					warning!("\"{}\" has {} tags, keeping the {} most frequent: {}", name, tags.len(), max_tags, ranked.join(", "));
+					tags.retain(|tag| ranked.contains(tag));
+				}
+				if let (true, Some(image)) = (args.tag_from_image, image) {
+					if !tags.contains(image) {
+						tags.push(image.clone());
+					}
+				}
+				if let Some(tag) = external_id_tag(args, job) {
+					tags.push(tag);
+				}
+				if let (true, Some(tag)) = (args.key_by_uid, uid_tag(job)) {
+					tags.push(tag);
+				}
+				tags.join(" ")
+			})
+			.collect()
+	};
+
+	plan.jobs = definitions.into_iter()
+		.zip(tags)
+		.map(|((job, name, schedule, _, tz), tags)| {
+			let floor = |value: Option<i32>, min: Option<i32>| match (value, min) {
+				(Some(value), Some(min)) => Some(value.max(min)),
+				(value, _) => value,
+			};
+			let check = NewCheck {
+				name: Some(name),
+				schedule: Some(schedule),
+				tags: Some(tags),
+				timeout: floor(default_check.timeout, args.hc.min_timeout),
+				grace: floor(default_check.grace, args.hc.min_grace),
+				tz: tz.or_else(|| default_check.tz.clone()),
+				..default_check.clone()
+			};
+			PlannedJob { job, check }
+		})
+		.collect();
+
+	if let Some(window) = args.modified_since {
+		let cutoff = Utc::now() - chrono::Duration::seconds(window as i64);
+		let (recent, stale) = std::mem::take(&mut plan.jobs)
+			.into_iter()
+			.partition(|planned| last_modified(&planned.job).is_some_and(|time| time >= cutoff));
+		plan.jobs = recent;
+		plan.skipped.extend(stale.into_iter().map(|planned: PlannedJob| (planned.name().to_string(), SkipReason::NotModified)));
+	}
+}
+
+/// The schedule of the job's healthkube.io/schedule annotation, if it has one.
+fn annotated_schedule(job: &CronJob) -> Result<Option<String>, ScheduleError> {
+	let Some(schedule) = annotation(job, SCHEDULE_ANNOTATION) else {
+		return Ok(None);
+	};
+	schedule::validate(schedule)?;
+	Ok(Some(schedule.to_string()))
+}
+
+/// The container that --tag-from-image and --tz-from-container-env read from: the --primary-container, or else the first one.
+fn primary_container<'a>(args: &Args, job: &str, containers: &'a [Container]) -> Option<&'a Container> {
+	let Some(primary) = &args.primary_container else {
+		return containers.first();
+	};
+	let found = containers.iter().find(|container| &container.name == primary);
+	if found.is_none() {
+		warning!("\"{}\" has no container named {}, so its first container is used", job, primary);
+	}
+	found.or_else(|| containers.first())
+}
+
+/// The timezone set by the container's literal CRON_TZ (or TZ) variable.
+fn container_timezone(container: &Container) -> Option<&str> {
+	let env = container.env.as_ref()?;
+	["CRON_TZ", "TZ"].into_iter().find_map(|key| {
+		env.iter()
+			.find(|var| var.name == key)
+			.and_then(|var| var.value.as_deref())
+			.filter(|value| !value.is_empty())
+	})
+}
+
+/// Whether `tz` names a zone in the tz database, e.g. "Europe/London".
+///
+/// It's looked up in the system's copy of the database, so without one, only the shape of the name is checked.
+pub fn is_known_timezone(tz: &str) -> bool {
+	const ZONEINFO: &str = "/usr/share/zoneinfo";
+
+	let well_formed = tz.split('/').all(|part| {
+		!part.is_empty() && part != "." && part != ".." && part.chars().all(|c| c.is_ascii_alphanumeric() || "_-+".contains(c))
+	});
+	if !well_formed {
+		return false;
+	}
+
+	let zoneinfo = std::path::Path::new(ZONEINFO);
+	!zoneinfo.is_dir() || zoneinfo.join(tz).is_file()
+}
+
+/// When the job was last modified: the latest of its creation, and every update recorded in its managed fields.
+fn last_modified(job: &CronJob) -> Option<DateTime<Utc>> {
+	let metadata = &job.metadata;
+	let created = metadata.creation_timestamp.as_ref().map(|time| time.0);
+	let updated = metadata.managed_fields.iter()
+		.flatten()
+		.filter_map(|entry| entry.time.as_ref())
+		.map(|time| time.0);
+	created.into_iter().chain(updated).max()
+}
+
+/// Sorts the segment frequencies by count (descending), and then by segment.
+fn tag_histogram(frequencies: &HashMap<&str, u8>) -> Vec<(String, u8)> {
+	let mut histogram: Vec<_> = frequencies.iter()
+		.map(|(segment, count)| (segment.to_string(), *count))
+		.collect();
+	histogram.sort_unstable_by(|(a_segment, a_count), (b_segment, b_count)| {
+		b_count.cmp(a_count).then_with(|| a_segment.cmp(b_segment))
+	});
+	histogram
+}
+
+/// The repository of an image reference, without the registry, path, tag or digest.
+fn image_repository(image: &str) -> Option<&str> {
+	let image = image.split('@').next()?;
+	let repository = image.rsplit('/').next()?;
+	let repository = repository.split(':').next()?;
+	(!repository.is_empty()).then_some(repository)
+}
+
+/// Whether the existing check already matches what's planned for it.
+pub fn is_up_to_date(planned: &NewCheck, existing: &Check) -> bool {
+	// Under --spec-hash, the tag alone tells, as long as the existing check has one too.
+	let spec_hash = |tags: &str| tags.split_whitespace().find(|tag| tag.starts_with(SPEC_HASH_PREFIX)).map(String::from);
+	if let (Some(planned), Some(existing)) = (
+		planned.tags.as_deref().and_then(spec_hash),
+		spec_hash(&existing.tags),
+	) {
+		return planned == existing;
+	}
+
+	let words = |value: &str| -> BTreeSet<String> {
+		value.split([' ', ',']).filter(|word| !word.is_empty()).map(String::from).collect()
+	};
+
+	let stamped = |value: &str| -> BTreeSet<String> {
+		words(value).into_iter().filter(|tag| !tag.starts_with(VERSION_PREFIX)).collect()
+	};
+	let tags = planned.tags.as_deref().is_none_or(|tags| stamped(tags) == stamped(&existing.tags));
+	let grace = planned.grace.is_none_or(|grace| i64::from(grace) == existing.grace);
+	let schedule = planned.schedule == existing.schedule;
+	let tz = planned.tz.as_ref().is_none_or(|tz| existing.tz.as_ref() == Some(tz));
+	// Only known when the API key can read them.
+	let channels = match (&planned.channels, &existing.channels) {
+		(Some(planned), Some(existing)) => words(planned) == words(existing),
+		_ => true,
+	};
+
+	tags && grace && schedule && tz && channels
+}
+
+/// The fields of the fetched check that don't hold what was sent, e.g. because HealthChecks coerced them.
+///
+/// Only the fields that were sent, and that HealthChecks reports back, are compared.
+pub fn mismatches(planned: &NewCheck, actual: &Check) -> Vec<&'static str> {
+	let words = |value: &str| -> BTreeSet<String> {
+		value.split([' ', ',']).filter(|word| !word.is_empty()).map(String::from).collect()
+	};
+
+	let mut fields = vec![];
+	if planned.schedule.is_some() && actual.schedule.is_some() && planned.schedule != actual.schedule {
+		fields.push("schedule");
+	}
+	if let (Some(tz), Some(actual)) = (&planned.tz, &actual.tz) {
+		if tz != actual {
+			fields.push("tz");
+		}
+	}
+	// Only simple (non-cron) checks report their timeout.
+	if let (Some(timeout), Some(actual)) = (planned.timeout, actual.timeout) {
+		if i64::from(timeout) != actual {
+			fields.push("timeout");
+		}
+	}
+	if planned.grace.is_some_and(|grace| i64::from(grace) != actual.grace) {
+		fields.push("grace");
+	}
+	if planned.tags.as_deref().is_some_and(|tags| words(tags) != words(&actual.tags)) {
+		fields.push("tags");
+	}
+	fields
+}
+
+/// The prefix of the tag that holds a hash of the check's spec, under --spec-hash.
+const SPEC_HASH_PREFIX: &str = "hkspec:";
+
+/// Tags every planned check with a hash of its spec, so an unchanged one is recognised by that single tag.
+///
+/// It has to run once the checks are final, after their channels are resolved.
+pub fn add_spec_hashes(plans: &mut [NamespacePlan]) {
+	for job in plans.iter_mut().flat_map(|plan| &mut plan.jobs) {
+		let tag = format!("{}{:016x}", SPEC_HASH_PREFIX, spec_hash(&job.check));
+		push_tag(&mut job.check, &tag);
+	}
+}
+
+/// The prefix of the tag that holds the version of healthkube that last changed the check, under --stamp-version.
+const VERSION_PREFIX: &str = "hkver:";
+
+/// Tags every planned check with this version of healthkube.
+///
+/// The tag is ignored when comparing checks (and added after the spec hash), so a new release alone doesn't update them.
+pub fn add_version_tags(plans: &mut [NamespacePlan]) {
+	let tag = format!("{}{}", VERSION_PREFIX, env!("CARGO_PKG_VERSION"));
+	for job in plans.iter_mut().flat_map(|plan| &mut plan.jobs) {
+		push_tag(&mut job.check, &tag);
+	}
+}
+
+fn push_tag(check: &mut NewCheck, tag: &str) {
+	let tags = check.tags.get_or_insert_with(String::new);
+	if !tags.is_empty() {
+		tags.push(' ');
+	}
+	tags.push_str(tag);
+}
+
+/// A FNV-1a hash of the check's JSON, which (unlike the std hashers) is stable between releases.
+fn spec_hash(check: &NewCheck) -> u64 {
+	let json = serde_json::to_string(check).unwrap_or_default();
+	json.bytes().fold(0xcbf2_9ce4_8422_2325, |hash, byte| (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3))
+}
+
+/// The tag that identifies a check by its CronJob, under --key-by-uid.
+pub fn uid_tag(job: &CronJob) -> Option<String> {
+	job.metadata.uid.as_ref().map(|uid| format!("uid:{}", uid))
+}
+
+/// The tag that identifies a check by the id in the job's --external-id-annotation ("extid:<id>").
+///
+/// Tags are separated by spaces, so an id with any whitespace in it is ignored.
+pub fn external_id_tag(args: &Args, job: &CronJob) -> Option<String> {
+	let id = annotation(job, args.external_id_annotation.as_deref()?)?.trim();
+	if id.is_empty() || id.contains(char::is_whitespace) {
+		return None;
+	}
+	Some(format!("extid:{}", id))
+}
+
+/// The tag that identifies the job's check, instead of its name: the external id, or under --key-by-uid, the uid.
+pub fn identity_tag(args: &Args, job: &CronJob) -> Option<String> {
+	external_id_tag(args, job).or_else(|| uid_tag(job).filter(|_| args.key_by_uid))
+}
+
+fn annotation<'a>(job: &'a CronJob, key: &str) -> Option<&'a str> {
+	job.metadata
+		.annotations
+		.as_ref()?
+		.get(key)
+		.map(String::as_str)
+}
+
+pub fn describe(job: &mut CronJob) -> Result<(&str, &str, &mut Vec<Container>), SkipReason> {
+	let CronJob {
+		spec,
+		metadata,
+		..
+	} = job;
+
+	let name = metadata.name.as_deref().ok_or(SkipReason::Unnamed)?;
+
+	let spec = spec.as_mut().ok_or(SkipReason::NoSpec)?;
+	let schedule = &*spec.schedule;
+	let spec = spec.job_template.spec.as_mut().ok_or(SkipReason::NoJobTemplate)?;
+	let spec = spec.template.spec.as_mut().ok_or(SkipReason::NoPodSpec)?;
+	let containers = &mut spec.containers;
+
+	Ok((name, schedule, containers))
+}
+
+#[cfg(test)]
+mod tests {
+	use k8s_openapi::apimachinery::pkg::apis::meta::v1::Time;
+
+	use super::*;
+	use crate::testing::{args, check, cron_job, job, namespace_plan, planned_job, Server};
+
+	#[test]
+	fn skips_a_job_without_a_job_template() {
+		let jobs = vec![
+			job(serde_json::json!({
+				"metadata": { "name": "nightly-report-job" },
+				"spec": { "schedule": "0 3 * * *", "jobTemplate": {} },
+			})),
+			cron_job("nightly-backup-job", "0 2 * * *"),
+		];
+		let mut plan = namespace_plan();
+		plan_jobs(&args(&["prod"]), &NewCheck::default(), jobs, &mut plan);
+
+		assert_eq!(plan.listed, 2);
+		assert_eq!(plan.skipped, [("nightly-report-job".to_string(), SkipReason::NoJobTemplate)]);
+		let planned: Vec<_> = plan.jobs.iter().map(PlannedJob::name).collect();
+		assert_eq!(planned, ["nightly-backup-job"]);
+	}
+
+	#[test]
+	fn histogram_is_sorted_by_count_then_segment() {
+		let frequencies = HashMap::from([("sync", 2), ("billing", 3), ("ops", 1), ("export", 2)]);
+		assert_eq!(tag_histogram(&frequencies), [
+			("billing".to_string(), 3),
+			("export".to_string(), 2),
+			("sync".to_string(), 2),
+			("ops".to_string(), 1),
+		]);
+	}
+	/// The tags planned for a handful of jobs, in order of their names.
+	fn planned_tags(flags: &[&str]) -> Vec<String> {
+		let jobs = ["billing-invoice-sync-job", "ops-report-sync-job", "billing-report-export-job", "billing-invoice-export-job"]
+			.into_iter()
+			.map(|name| cron_job(name, "0 2 * * *"))
+			.collect();
+		let mut plan = namespace_plan();
+		let args = args(&[flags, &["prod"]].concat());
+		plan_jobs(&args, &NewCheck::default(), jobs, &mut plan);
+		plan.jobs.into_iter()
+			.map(|planned| planned.check.tags.unwrap_or_default())
+			.collect()
+	}
+
+	#[test]
+	fn tags_the_common_segments() {
+		assert_eq!(planned_tags(&["--rank", "2"]), [
+			"billing invoice export",
+			"billing invoice sync",
+			"billing report export",
+			"report sync",
+		]);
+	}
+
+	#[test]
+	fn tags_only_the_allowed_segments() {
+		assert_eq!(planned_tags(&["--rank", "2", "--tag-allowlist", "billing", "--tag-allowlist", "ops"]), ["billing", "billing", "billing", ""]);
+		assert_eq!(planned_tags(&["--rank", "1", "--tag-allowlist", "billing", "--tag-allowlist", "ops"]), ["billing", "billing", "billing", "ops"]);
+	}
+
+	#[test]
+	fn never_tags_the_denied_segments() {
+		assert_eq!(planned_tags(&["--rank", "2", "--tag-denylist", "billing"]), [
+			"invoice export",
+			"invoice sync",
+			"report export",
+			"report sync",
+		]);
+	}
+
+	#[test]
+	fn sorts_the_jobs_by_name() {
+		let jobs = ["reindex-job", "backup-job", "cleanup-job", "archive-job"]
+			.into_iter()
+			.map(|name| cron_job(name, "0 2 * * *"))
+			.collect();
+		let mut plan = namespace_plan();
+		plan_jobs(&args(&["prod"]), &NewCheck::default(), jobs, &mut plan);
+
+		let planned: Vec<_> = plan.jobs.iter().map(PlannedJob::name).collect();
+		assert_eq!(planned, ["archive-job", "backup-job", "cleanup-job", "reindex-job"]);
+	}
+
+	#[test]
+	fn skips_a_job_with_an_invalid_schedule_annotation() {
+		let mut job = cron_job("nightly-backup-job", "0 2 * * *");
+		job.metadata.annotations = Some([(SCHEDULE_ANNOTATION.to_string(), "every night".to_string())].into());
+		let mut plan = namespace_plan();
+		plan_jobs(&args(&["prod"]), &NewCheck::default(), vec![job], &mut plan);
+
+		assert!(plan.jobs.is_empty());
+		assert_eq!(plan.skipped, [("nightly-backup-job".to_string(), SkipReason::InvalidSchedule)]);
+	}
+
+	#[test]
+	fn skips_the_jobs_that_werent_modified_recently() {
+		let modified = |name: &str, days: i64| {
+			let mut job = cron_job(name, "0 2 * * *");
+			job.metadata.creation_timestamp = Some(Time(Utc::now() - chrono::Duration::days(days)));
+			job
+		};
+		let mut plan = namespace_plan();
+		plan_jobs(&args(&["--modified-since", "7d", "prod"]), &NewCheck::default(), vec![modified("old-job", 30), modified("new-job", 1)], &mut plan);
+
+		assert_eq!(plan.jobs.iter().map(PlannedJob::name).collect::<Vec<_>>(), ["new-job"]);
+		assert_eq!(plan.skipped, [("old-job".to_string(), SkipReason::NotModified)]);
+	}
+
+	#[test]
+	fn skips_a_job_with_an_empty_schedule() {
+		let warnings = crate::output::warnings();
+		let mut plan = namespace_plan();
+		plan_jobs(&args(&["prod"]), &NewCheck::default(), vec![cron_job("nightly-backup-job", "  ")], &mut plan);
+
+		assert!(plan.jobs.is_empty());
+		assert_eq!(plan.skipped, [("nightly-backup-job".to_string(), SkipReason::EmptySchedule)]);
+		assert!(crate::output::warnings() > warnings);
+	}
+
+	#[test]
+	fn the_grace_is_kept_above_the_floor() {
+		let default_check = NewCheck {
+			grace: Some(60),
+			..Default::default()
+		};
+		let mut plan = namespace_plan();
+		plan_jobs(&args(&["--min-grace", "120", "prod"]), &default_check, vec![cron_job("nightly-backup-job", "* * * * *")], &mut plan);
+		assert_eq!(plan.jobs[0].check.grace, Some(120));
+
+		let mut plan = namespace_plan();
+		plan_jobs(&args(&["--min-grace", "120", "prod"]), &NewCheck { grace: Some(600), ..default_check }, vec![cron_job("nightly-backup-job", "* * * * *")], &mut plan);
+		assert_eq!(plan.jobs[0].check.grace, Some(600));
+	}
+
+	#[test]
+	fn stamps_the_version_without_changing_the_check() {
+		let mut plans = vec![NamespacePlan {
+			jobs: vec![planned_job("nightly-backup-job")],
+			..namespace_plan()
+		}];
+		add_version_tags(&mut plans);
+		let planned = &plans[0].jobs[0].check;
+		let stamp = format!("hkver:{}", env!("CARGO_PKG_VERSION"));
+		assert_eq!(planned.tags.as_deref(), Some(stamp.as_str()));
+
+		let mut existing = check("a1", "nightly-backup-job", "hkver:0.0.1");
+		existing.schedule = planned.schedule.clone();
+		assert!(is_up_to_date(planned, &existing));
+	}
+
+	#[test]
+	fn caps_the_tags_at_the_most_frequent() {
+		let jobs = vec![cron_job("a-b-c-d-e-f-g-h-i-k", "0 2 * * *"), cron_job("f-g-h", "0 2 * * *")];
+		let mut plan = namespace_plan();
+		plan_jobs(&args(&["--rank", "1", "--max-tags", "3", "prod"]), &NewCheck::default(), jobs, &mut plan);
+
+		let capped = plan.jobs.iter().find(|job| job.name() == "a-b-c-d-e-f-g-h-i-k").unwrap();
+		assert_eq!(capped.check.tags.as_deref(), Some("f g h"));
+	}
+
+	/// A CronJob running the `containers`, each given as its name and TZ variable.
+	fn job_with_timezones(containers: &[(&str, &str)]) -> CronJob {
+		let containers: Vec<_> = containers.iter()
+			.map(|(name, tz)| serde_json::json!({
+				"name": name,
+				"image": format!("registry.example.com/billing/{}:1.0", name),
+				"env": [{ "name": "TZ", "value": tz }],
+			}))
+			.collect();
+		job(serde_json::json!({
+			"metadata": { "name": "nightly-backup-job", "namespace": "billing" },
+			"spec": {
+				"schedule": "0 2 * * *",
+				"jobTemplate": { "spec": { "template": { "spec": { "containers": containers } } } },
+			},
+		}))
+	}
+
+	#[test]
+	fn takes_the_timezone_from_the_container_env() {
+		let mut plan = namespace_plan();
+		plan_jobs(&args(&["--tz-from-container-env", "prod"]), &NewCheck::default(), vec![job_with_timezones(&[("app", "Europe/London")])], &mut plan);
+		assert_eq!(plan.jobs[0].check.tz.as_deref(), Some("Europe/London"));
+
+		let mut plan = namespace_plan();
+		plan_jobs(&args(&["prod"]), &NewCheck::default(), vec![job_with_timezones(&[("app", "Europe/London")])], &mut plan);
+		assert_eq!(plan.jobs[0].check.tz, None);
+	}
+
+	#[test]
+	fn reads_the_primary_container() {
+		let jobs = || vec![job_with_timezones(&[("istio-proxy", "UTC"), ("app", "Europe/London")])];
+
+		let mut plan = namespace_plan();
+		plan_jobs(&args(&["--tz-from-container-env", "--tag-from-image", "--primary-container", "app", "prod"]), &NewCheck::default(), jobs(), &mut plan);
+		assert_eq!(plan.jobs[0].check.tz.as_deref(), Some("Europe/London"));
+		assert!(plan.jobs[0].check.tags.as_deref().unwrap().split(' ').any(|tag| tag == "app"));
+
+		let mut plan = namespace_plan();
+		plan_jobs(&args(&["--tz-from-container-env", "prod"]), &NewCheck::default(), jobs(), &mut plan);
+		assert_eq!(plan.jobs[0].check.tz.as_deref(), Some("UTC"));
+	}
+
+	#[test]
+	fn reports_a_grace_that_didnt_take() {
+		let planned = NewCheck {
+			grace: Some(900),
+			tags: Some("billing".into()),
+			..Default::default()
+		};
+		assert_eq!(mismatches(&planned, &check("a1", "nightly-backup-job", "billing")), ["grace"]);
+		assert!(mismatches(&NewCheck { grace: Some(3600), ..planned }, &check("a1", "nightly-backup-job", "billing")).is_empty());
+	}
+
+	#[test]
+	fn expands_the_context_pattern() {
+		let available: Vec<_> = ["prod-a", "prod-b", "dev"].map(String::from).into();
+		assert_eq!(expand_context("prod-*:billing", &available).unwrap(), ["prod-a:billing", "prod-b:billing"]);
+		assert_eq!(expand_context("~^prod-b$:billing;grace=60", &available).unwrap(), ["prod-b:billing;grace=60"]);
+		assert_eq!(expand_context("dev:billing", &available).unwrap(), ["dev:billing"]);
+		assert_eq!(expand_context("staging-*:billing", &available).unwrap(), ["staging-*:billing"]);
+	}
+
+	#[tokio::test]
+	async fn rejects_invalid_targets() {
+		crate::testing::cluster();
+
+		let err = plan(&args(&["prod-a"]), &["prod-a:billing;timeout=soon".into()], &NewCheck::default()).await.err().expect("an error");
+		assert!(matches!(err, SyncError::Target { .. }), "{:?}", err);
+
+		let err = plan(&args(&["prod-a"]), &["prod-a:~(billing".into()], &NewCheck::default()).await.err().expect("an error");
+		assert!(matches!(err, SyncError::Pattern { .. }), "{:?}", err);
+	}
+
+	#[tokio::test]
+	async fn rejects_a_missing_fixture() {
+		crate::testing::cluster();
+
+		let args = args(&["--dry-run", "--offline", "/nonexistent/cronjobs.json", "prod-a"]);
+		let err = plan(&args, &args.targets, &NewCheck::default()).await.err().expect("an error");
+		assert!(matches!(err, SyncError::Fixture { .. }), "{:?}", err);
+	}
+
+	#[test]
+	fn the_schedule_annotation_overrides_the_schedule() {
+		let mut job = cron_job("nightly-backup-job", "0 2 * * *");
+		job.metadata.annotations = Some([(SCHEDULE_ANNOTATION.to_string(), "30 4 * * *".to_string())].into());
+		let mut plan = namespace_plan();
+		plan_jobs(&args(&["prod"]), &NewCheck::default(), vec![job], &mut plan);
+
+		assert_eq!(plan.jobs[0].check.schedule.as_deref(), Some("30 4 * * *"));
+	}
+
+	#[test]
+	fn passes_the_label_selector_on() {
+		let params = list_params(&args(&["--label-selector", "team=billing,tier!=dev", "prod"]));
+		assert_eq!(params.label_selector.as_deref(), Some("team=billing,tier!=dev"));
+
+		assert_eq!(list_params(&args(&["prod"])).label_selector, None);
+	}
+
+	#[test]
+	fn passes_the_field_selector_on() {
+		let params = list_params(&args(&["--field-selector", "metadata.name!=cleanup-job", "prod"]));
+		assert_eq!(params.field_selector.as_deref(), Some("metadata.name!=cleanup-job"));
+
+		assert_eq!(list_params(&args(&["prod"])).field_selector, None);
+	}
+	async fn namespaces(flags: &[&str], namespaces: &str) -> Vec<String> {
+		let client = crate::testing::cluster().kube_client();
+		let args = args(&[flags, &["prod-a"]].concat());
+		let pattern = namespace_pattern(namespaces).unwrap();
+		resolve_namespaces(&args, "prod-a", &client, namespaces, pattern.as_ref()).await
+			.unwrap()
+			.into_iter()
+			.map(|(namespace, _)| namespace)
+			.collect()
+	}
+
+	#[tokio::test]
+	async fn excludes_namespaces_from_the_wildcard() {
+		assert_eq!(namespaces(&[], "*").await, ["billing", "infra", "team-a", "team-b"]);
+		assert_eq!(namespaces(&["--exclude-namespace", "infra"], "*").await, ["billing", "team-a", "team-b"]);
+		assert_eq!(namespaces(&["--exclude-namespace-regex", "^team-"], "*").await, ["billing", "infra"]);
+	}
+
+	#[tokio::test]
+	async fn matches_namespaces_by_glob_or_regex() {
+		assert_eq!(namespaces(&[], "team-*").await, ["team-a", "team-b"]);
+		assert_eq!(namespaces(&[], "~^(billing|infra)$").await, ["billing", "infra"]);
+	}
+
+	#[tokio::test]
+	async fn lists_the_namespaces_by_the_namespace_selector() {
+		let cluster = Server::start(|_| (200, serde_json::json!({ "metadata": {}, "items": [] })));
+		let args = args(&["--namespace-selector", "healthkube=enabled", "prod-a"]);
+		list_namespaces(&args, "prod-a", &cluster.kube_client()).await.unwrap();
+
+		let requests = cluster.requests();
+		assert_eq!(requests.len(), 1);
+		assert!(requests[0].path.starts_with("/api/v1/namespaces?"), "{}", requests[0].path);
+		assert!(requests[0].path.contains("labelSelector=healthkube%3Denabled"), "{}", requests[0].path);
+	}
+
+	#[tokio::test]
+	async fn retries_a_failed_page_from_the_same_continue_token() {
+		let served = std::sync::atomic::AtomicUsize::new(0);
+		let cluster = Server::start(move |_| {
+			let page = |name: &str, next: &str| serde_json::json!({ "metadata": { "continue": next }, "items": [{ "metadata": { "name": name } }] });
+			match served.fetch_add(1, Ordering::Relaxed) {
+				0 => (200, page("billing", "page-2")),
+				1 => (500, serde_json::json!({ "kind": "Status", "status": "Failure", "reason": "InternalError", "code": 500 })),
+				_ => (200, page("infra", "")),
+			}
+		});
+		let namespaces = list_namespaces(&args(&["prod-a"]), "prod-a", &cluster.kube_client()).await.unwrap();
+
+		assert_eq!(namespaces, [("billing".to_string(), false), ("infra".to_string(), false)]);
+		let paths: Vec<_> = cluster.requests().into_iter().map(|request| request.path).collect();
+		assert_eq!(paths.len(), 3);
+		assert!(!paths[0].contains("continue="), "{}", paths[0]);
+		assert!(paths[1].contains("continue=page-2"), "{}", paths[1]);
+		assert_eq!(paths[1], paths[2]);
+	}
+
+	#[tokio::test]
+	async fn tells_which_namespaces_are_being_deleted() {
+		let cluster = Server::start(|_| (200, serde_json::json!({
+			"metadata": {},
+			"items": [
+				{ "metadata": { "name": "billing" } },
+				{ "metadata": { "name": "team-a", "deletionTimestamp": "2026-10-15T02:00:00Z" } },
+			],
+		})));
+		let namespaces = list_namespaces(&args(&["prod-a"]), "prod-a", &cluster.kube_client()).await.unwrap();
+		assert_eq!(namespaces, [("billing".to_string(), false), ("team-a".to_string(), true)]);
+	}
+
+	#[test]
+	fn stops_retrying_once_the_budget_is_spent() {
+		// The only test with a --retry-budget, as the retries are counted across the whole run.
+		let args = args(&["--retry-budget", "2", "prod-a"]);
+		assert!(take_retry(&args));
+		assert!(take_retry(&args));
+		assert!(!take_retry(&args));
+	}
+
+	#[tokio::test]
+	async fn rejects_unknown_contexts() {
+		crate::testing::cluster();
+
+		let err = plan(&args(&["prod-a"]), &["staging:billing".into(), "prod-a:billing".into()], &NewCheck::default()).await.err().expect("an error");
+		let SyncError::UnknownContexts { missing, available } = err else {
+			panic!("{:?}", err);
+		};
+		assert_eq!(missing, ["staging"]);
+		assert_eq!(available, ["prod-a", "prod-b", "dev"]);
+	}
+
+	#[test]
+	fn image_repository_drops_the_registry_tag_and_digest() {
+		assert_eq!(image_repository("registry.example.com/team/billing:1.2"), Some("billing"));
+		assert_eq!(image_repository("registry.example.com:5000/billing"), Some("billing"));
+		assert_eq!(image_repository("billing@sha256:abc"), Some("billing"));
+		assert_eq!(image_repository("nginx"), Some("nginx"));
+		assert_eq!(image_repository("registry.example.com/"), None);
+	}
+
+	#[test]
+	fn tags_the_image_repository() {
+		assert_eq!(planned_tags(&["--rank", "2", "--tag-allowlist", "ops", "--tag-from-image"]), ["app", "app", "app", "app"]);
+	}
+}
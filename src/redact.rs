@@ -0,0 +1,50 @@
+use std::sync::OnceLock;
+
+use regex::Regex;
+
+const REDACTED: &str = "***";
+
+/// Replaces every occurrence of the `secrets`, and the values of any credential headers, with `***`.
+///
+/// Errors from the HTTP clients can embed request details, so anything that's shown to the user goes through here first.
+pub fn redact(message: &str, secrets: &[&str]) -> String {
+	static HEADERS: OnceLock<Regex> = OnceLock::new();
+	let headers = HEADERS.get_or_init(|| {
+		Regex::new(r#"(?i)\b(authorization|x-api-key)(["']?\s*[:=]\s*["']?)((?:bearer|basic)\s+)?[^\s"',}]+"#)
+			.unwrap()
+	});
+
+	let mut message = headers
+		.replace_all(message, format!("${{1}}${{2}}${{3}}{}", REDACTED))
+		.into_owned();
+
+	for secret in secrets {
+		if !secret.is_empty() {
+			message = message.replace(secret, REDACTED);
+		}
+	}
+
+	message
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn redacts_the_secrets() {
+		assert_eq!(redact("key abc123 was rejected (abc123)", &["abc123"]), "key *** was rejected (***)");
+	}
+
+	#[test]
+	fn ignores_empty_secrets() {
+		assert_eq!(redact("nothing to hide", &[""]), "nothing to hide");
+	}
+
+	#[test]
+	fn redacts_credential_headers() {
+		assert_eq!(redact("Authorization: Bearer token.value", &[]), "Authorization: Bearer ***");
+		assert_eq!(redact(r#"{"X-Api-Key": "secret"}"#, &[]), r#"{"X-Api-Key": "***"}"#);
+		assert_eq!(redact("x-api-key=secret, next", &[]), "x-api-key=***, next");
+	}
+}
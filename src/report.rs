@@ -0,0 +1,295 @@
+use std::collections::BTreeMap;
+
+use healthchecks::model::{Check, NewCheck};
+use k8s_openapi::chrono::{SecondsFormat, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::plan::{NamespacePlan, PlannedJob};
+use crate::summary::Summary;
+
+/// Bumped whenever the shape of the plan changes in a way that could break consumers.
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// The machine-readable version of a dry-run.
+#[derive(Serialize)]
+pub struct PlanDocument<'a> {
+	pub schema_version: u32,
+	pub generated_at: String,
+	pub targets: Vec<TargetPlan<'a>>,
+	/// The checks that --clear-existing-checks would delete.
+	pub deletions: Vec<PlannedDeletion<'a>>,
+}
+
+#[derive(Serialize)]
+pub struct TargetPlan<'a> {
+	pub context: &'a str,
+	pub namespaces: Vec<NamespaceActions<'a>>,
+}
+
+#[derive(Serialize)]
+pub struct NamespaceActions<'a> {
+	pub namespace: &'a str,
+	pub actions: Vec<JobAction<'a>>,
+	pub skipped: Vec<SkippedJob<'a>>,
+}
+
+#[derive(Serialize)]
+pub struct JobAction<'a> {
+	pub job: &'a str,
+	pub action: PlannedAction,
+	pub check: &'a NewCheck,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum PlannedAction {
+	Create,
+	Update,
+	Unchanged,
+}
+
+impl std::fmt::Display for PlannedAction {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		f.pad(match self {
+			PlannedAction::Create => "create",
+			PlannedAction::Update => "update",
+			PlannedAction::Unchanged => "unchanged",
+		})
+	}
+}
+
+#[derive(Serialize)]
+pub struct PlannedDeletion<'a> {
+	pub name: &'a str,
+	pub check: &'a str,
+}
+
+#[derive(Serialize)]
+pub struct SkippedJob<'a> {
+	pub job: &'a str,
+	pub reason: String,
+}
+
+impl<'a> PlanDocument<'a> {
+	/// `existing` finds the check that a job would update (or leave alone), rather than create.
+	pub fn new<'c>(
+		plans: &'a [NamespacePlan],
+		deletions: Vec<PlannedDeletion<'a>>,
+		existing: impl Fn(&NamespacePlan, &PlannedJob) -> Option<&'c Check>,
+	) -> Self {
+		let mut targets: Vec<TargetPlan> = vec![];
+
+		for plan in plans {
+			let actions = plan.jobs.iter()
+				.map(|job| {
+					let action = match existing(plan, job) {
+						Some(check) if crate::plan::is_up_to_date(&job.check, check) => PlannedAction::Unchanged,
+						Some(_) => PlannedAction::Update,
+						None => PlannedAction::Create,
+					};
+					JobAction {
+						job: job.name(),
+						action,
+						check: &job.check,
+					}
+				})
+				.collect();
+
+			let skipped = plan.skipped.iter()
+				.map(|(job, reason)| SkippedJob {
+					job,
+					reason: reason.to_string(),
+				})
+				.collect();
+
+			let namespace = NamespaceActions {
+				namespace: &plan.namespace,
+				actions,
+				skipped,
+			};
+
+			match targets.last_mut() {
+				Some(target) if target.context == plan.context => target.namespaces.push(namespace),
+				_ => targets.push(TargetPlan {
+					context: &plan.context,
+					namespaces: vec![namespace],
+				}),
+			}
+		}
+
+		PlanDocument {
+			schema_version: SCHEMA_VERSION,
+			generated_at: Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true),
+			targets,
+			deletions,
+		}
+	}
+}
+
+/// Just enough of a serialised [PlanDocument] to tell what it would do to each job, for --plan-since.
+#[derive(Deserialize, Default)]
+pub struct SavedPlan {
+	targets: Vec<SavedTarget>,
+}
+
+#[derive(Deserialize)]
+struct SavedTarget {
+	context: String,
+	namespaces: Vec<SavedNamespace>,
+}
+
+#[derive(Deserialize)]
+struct SavedNamespace {
+	namespace: String,
+	actions: Vec<SavedAction>,
+}
+
+#[derive(Deserialize)]
+struct SavedAction {
+	job: String,
+	action: PlannedAction,
+}
+
+/// A job, as "context/namespace: job".
+type JobKey = String;
+
+/// How a plan differs from the previous one.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Change {
+	/// A create or update that the previous plan didn't have (or had as the other one).
+	New(PlannedAction),
+	/// A create or update of the previous plan that's no longer needed.
+	Resolved(PlannedAction),
+}
+
+impl SavedPlan {
+	fn actions(&self) -> BTreeMap<JobKey, PlannedAction> {
+		self.targets.iter()
+			.flat_map(|target| target.namespaces.iter().map(move |namespace| (&target.context, namespace)))
+			.flat_map(|(context, namespace)| namespace.actions.iter().map(move |action| {
+				(format!("{}/{}: {}", context, namespace.namespace, action.job), action.action)
+			}))
+			.collect()
+	}
+
+	/// What's changed in `current`, since this plan.
+	pub fn changes(&self, current: &SavedPlan) -> Vec<(JobKey, Change)> {
+		let previous = self.actions();
+		let current = current.actions();
+
+		let mut changes = vec![];
+		for (job, action) in &current {
+			if *action != PlannedAction::Unchanged && previous.get(job) != Some(action) {
+				changes.push((job.clone(), Change::New(*action)));
+			}
+		}
+		for (job, action) in &previous {
+			let pending = current.get(job).is_some_and(|action| *action != PlannedAction::Unchanged);
+			if *action != PlannedAction::Unchanged && !pending {
+				changes.push((job.clone(), Change::Resolved(*action)));
+			}
+		}
+		changes.sort_by(|(a, _), (b, _)| a.cmp(b));
+		changes
+	}
+}
+
+/// The machine-readable version of a run, with the outcome of every job.
+#[derive(Serialize)]
+pub struct ResultDocument<'a> {
+	pub schema_version: u32,
+	pub generated_at: String,
+	#[serde(flatten)]
+	pub summary: &'a Summary,
+}
+
+impl<'a> ResultDocument<'a> {
+	pub fn new(summary: &'a Summary) -> Self {
+		ResultDocument {
+			schema_version: SCHEMA_VERSION,
+			generated_at: Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true),
+			summary,
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::testing::{namespace_plan, planned_job};
+
+	fn saved(actions: &[(&str, &str)]) -> SavedPlan {
+		let actions: Vec<_> = actions.iter()
+			.map(|(job, action)| serde_json::json!({ "job": job, "action": action, "check": {} }))
+			.collect();
+		serde_json::from_value(serde_json::json!({
+			"schema_version": SCHEMA_VERSION,
+			"targets": [{ "context": "prod", "namespaces": [{ "namespace": "billing", "actions": actions, "skipped": [] }] }],
+		}))
+		.unwrap()
+	}
+
+	#[test]
+	fn plan_documents_are_versioned() {
+		let mut plan = namespace_plan();
+		plan.jobs.push(planned_job("nightly-backup-job"));
+		let plans = [plan];
+		let document = serde_json::to_value(PlanDocument::new(&plans, vec![], |_, _| None)).unwrap();
+
+		assert_eq!(document["schema_version"], SCHEMA_VERSION);
+		assert!(document["generated_at"].as_str().is_some_and(|at| at.ends_with('Z')), "{}", document["generated_at"]);
+		assert_eq!(document["targets"], serde_json::json!([{
+			"context": "prod",
+			"namespaces": [{
+				"namespace": "billing",
+				"actions": [{
+					"job": "nightly-backup-job",
+					"action": "create",
+					"check": { "name": "nightly-backup-job", "schedule": "0 2 * * *" },
+				}],
+				"skipped": [],
+			}],
+		}]));
+	}
+
+	#[test]
+	fn plan_documents_list_the_deletions() {
+		let deletions = vec![PlannedDeletion {
+			name: "old-backup-job",
+			check: "f6a2",
+		}];
+		let document = serde_json::to_value(PlanDocument::new(&[], deletions, |_, _| None)).unwrap();
+		assert_eq!(document["deletions"], serde_json::json!([{ "name": "old-backup-job", "check": "f6a2" }]));
+	}
+
+	#[test]
+	fn nothing_changed() {
+		let plan = saved(&[("a", "create"), ("b", "unchanged")]);
+		assert!(plan.changes(&saved(&[("a", "create"), ("b", "unchanged")])).is_empty());
+	}
+
+	#[test]
+	fn lists_new_and_resolved_actions() {
+		let previous = saved(&[("a", "create"), ("b", "update"), ("c", "unchanged"), ("d", "update")]);
+		let current = saved(&[("a", "update"), ("b", "unchanged"), ("c", "create")]);
+		assert_eq!(previous.changes(&current), vec![
+			("prod/billing: a".to_string(), Change::New(PlannedAction::Update)),
+			("prod/billing: b".to_string(), Change::Resolved(PlannedAction::Update)),
+			("prod/billing: c".to_string(), Change::New(PlannedAction::Create)),
+			("prod/billing: d".to_string(), Change::Resolved(PlannedAction::Update)),
+		]);
+	}
+
+	#[test]
+	fn everything_is_new_without_a_previous_plan() {
+		let current = saved(&[("a", "create"), ("b", "unchanged")]);
+		assert_eq!(SavedPlan::default().changes(&current), vec![
+			("prod/billing: a".to_string(), Change::New(PlannedAction::Create)),
+		]);
+	}
+
+	#[test]
+	fn planned_actions_pad() {
+		assert_eq!(format!("[{: <8}]", PlannedAction::Create), "[create  ]");
+	}
+}
@@ -0,0 +1,158 @@
+/// Why a cron expression was rejected.
+#[derive(Debug, thiserror::Error)]
+pub enum ScheduleError {
+	#[error("expected 5 fields, found {0}")]
+	FieldCount(usize),
+
+	#[error("invalid {field} field: \"{value}\"")]
+	InvalidField {
+		field: &'static str,
+		value: String,
+	},
+
+	#[error("unknown macro: \"{0}\"")]
+	UnknownMacro(String),
+}
+
+struct Field {
+	name: &'static str,
+	min: u32,
+	max: u32,
+	/// Names that can be used instead of numbers, starting at `min`.
+	names: &'static [&'static str],
+}
+
+const FIELDS: [Field; 5] = [
+	Field { name: "minute", min: 0, max: 59, names: &[] },
+	Field { name: "hour", min: 0, max: 23, names: &[] },
+	Field { name: "day of month", min: 1, max: 31, names: &[] },
+	Field {
+		name: "month",
+		min: 1,
+		max: 12,
+		names: &["jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec"],
+	},
+	Field {
+		name: "day of week",
+		min: 0,
+		max: 7,
+		names: &["sun", "mon", "tue", "wed", "thu", "fri", "sat"],
+	},
+];
+
+const MACROS: [&str; 7] = ["@yearly", "@annually", "@monthly", "@weekly", "@daily", "@midnight", "@hourly"];
+
+/// Checks that `expression` is a standard (5 field) cron expression, like the ones Healthchecks accepts.
+pub fn validate(expression: &str) -> Result<(), ScheduleError> {
+	let expression = expression.trim();
+
+	if expression.starts_with('@') {
+		if !MACROS.contains(&expression) {
+			return Err(ScheduleError::UnknownMacro(expression.into()));
+		}
+		return Ok(());
+	}
+
+	let values: Vec<_> = expression.split_whitespace().collect();
+	if values.len() != FIELDS.len() {
+		return Err(ScheduleError::FieldCount(values.len()));
+	}
+
+	for (field, value) in FIELDS.iter().zip(values) {
+		if !field.accepts(value) {
+			return Err(ScheduleError::InvalidField {
+				field: field.name,
+				value: value.into(),
+			});
+		}
+	}
+
+	Ok(())
+}
+
+impl Field {
+	/// Accepts lists of `*`, `a` or `a-b`, each optionally followed by a `/step`.
+	fn accepts(&self, value: &str) -> bool {
+		value.split(',').all(|item| {
+			let (range, step) = match item.split_once('/') {
+				Some((range, step)) => (range, Some(step)),
+				None => (item, None),
+			};
+
+			if let Some(step) = step {
+				if !matches!(step.parse::<u32>(), Ok(step) if step > 0) {
+					return false;
+				}
+			}
+
+			if range == "*" {
+				return true;
+			}
+
+			let (start, end) = match range.split_once('-') {
+				Some((start, end)) => (start, Some(end)),
+				None => (range, None),
+			};
+
+			let Some(start) = self.value(start) else {
+				return false;
+			};
+
+			match end {
+				Some(end) => matches!(self.value(end), Some(end) if start <= end),
+				None => true,
+			}
+		})
+	}
+
+	fn value(&self, value: &str) -> Option<u32> {
+		if let Some(index) = self.names.iter().position(|name| name.eq_ignore_ascii_case(value)) {
+			return Some(self.min + index as u32);
+		}
+		value.parse()
+			.ok()
+			.filter(|value| (self.min..=self.max).contains(value))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn accepts_standard_expressions() {
+		for expression in ["* * * * *", "*/5 * * * *", "0 9-17 * * mon-fri", "15,45 0 1 JAN,jul 0", "0 0 * * 7", " @daily "] {
+			assert!(validate(expression).is_ok(), "{}", expression);
+		}
+	}
+
+	#[test]
+	fn rejects_the_wrong_number_of_fields() {
+		assert!(matches!(validate("* * * *"), Err(ScheduleError::FieldCount(4))));
+		assert!(matches!(validate("0 * * * * *"), Err(ScheduleError::FieldCount(6))));
+	}
+
+	#[test]
+	fn rejects_invalid_fields() {
+		let cases = [
+			("60 * * * *", "minute"),
+			("* 24 * * *", "hour"),
+			("* * 0 * *", "day of month"),
+			("* * * foo *", "month"),
+			("* * * * 8", "day of week"),
+			("*/0 * * * *", "minute"),
+			("5-1 * * * *", "minute"),
+		];
+		for (expression, expected) in cases {
+			match validate(expression) {
+				Err(ScheduleError::InvalidField { field, .. }) => assert_eq!(field, expected, "{}", expression),
+				other => panic!("{}: {:?}", expression, other),
+			}
+		}
+	}
+
+	#[test]
+	fn rejects_unknown_macros() {
+		assert!(matches!(validate("@reboot"), Err(ScheduleError::UnknownMacro(name)) if name == "@reboot"));
+	}
+}
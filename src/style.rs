@@ -0,0 +1,89 @@
+use owo_colors::OwoColorize;
+
+/// What happened to a check (or job), as shown to the user.
+#[derive(Debug, Clone, Copy)]
+pub enum Status {
+	Created,
+	Updated,
+	Unchanged,
+	Deleted,
+	Paused,
+	Skipped,
+	Failed,
+}
+
+impl Status {
+	fn label(self) -> &'static str {
+		match self {
+			Status::Created => "Created",
+			Status::Updated => "Updated",
+			Status::Unchanged => "Unchanged",
+			Status::Deleted => "Deleted",
+			Status::Paused => "Paused",
+			Status::Skipped => "Skipped",
+			Status::Failed => "Failed",
+		}
+	}
+
+	/// Renders the status, and colours it if requested.
+	pub fn paint(self, color: bool) -> String {
+		let label = self.label();
+		if !color {
+			return label.into();
+		}
+		match self {
+			Status::Created => label.green().to_string(),
+			Status::Updated => label.yellow().to_string(),
+			Status::Unchanged => label.bright_black().to_string(),
+			Status::Deleted => label.red().to_string(),
+			Status::Paused => label.blue().to_string(),
+			Status::Skipped => label.bright_black().to_string(),
+			Status::Failed => label.red().bold().to_string(),
+		}
+	}
+}
+
+/// When to colour the output.
+#[derive(clap::ArgEnum, Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ColorChoice {
+	Auto,
+	Always,
+	Never,
+}
+
+/// Under "auto", colours are only used when stdout is a terminal, and the user hasn't opted out.
+///
+/// See: https://no-color.org/
+pub fn use_color(choice: ColorChoice, is_tty: bool) -> bool {
+	match choice {
+		ColorChoice::Always => true,
+		ColorChoice::Never => false,
+		ColorChoice::Auto => {
+			let no_color_env = std::env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty());
+			!no_color_env && is_tty
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn follows_an_explicit_choice() {
+		assert!(use_color(ColorChoice::Always, false));
+		assert!(!use_color(ColorChoice::Never, true));
+	}
+
+	#[test]
+	fn needs_a_terminal_under_auto() {
+		assert!(!use_color(ColorChoice::Auto, false));
+	}
+
+	#[test]
+	fn only_paints_when_asked_to() {
+		assert_eq!(Status::Created.paint(false), "Created");
+		assert_ne!(Status::Created.paint(true), "Created");
+	}
+}
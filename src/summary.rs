@@ -0,0 +1,217 @@
+use std::collections::BTreeMap;
+use std::io::Write;
+
+use serde::Serialize;
+
+use crate::plan::SkipReason;
+use crate::output::say;
+use crate::style::Status;
+
+/// What happened to a single job (or check), shared by the text and JSON output.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum Action {
+	Created,
+	Updated,
+	Unchanged,
+	Deleted,
+	Paused,
+	Skipped {
+		#[serde(serialize_with = "serialize_display")]
+		reason: SkipReason,
+	},
+	Failed {
+		error: String,
+	},
+}
+
+impl Action {
+	pub fn status(&self) -> Status {
+		match self {
+			Action::Created => Status::Created,
+			Action::Updated => Status::Updated,
+			Action::Unchanged => Status::Unchanged,
+			Action::Deleted => Status::Deleted,
+			Action::Paused => Status::Paused,
+			Action::Skipped { .. } => Status::Skipped,
+			Action::Failed { .. } => Status::Failed,
+		}
+	}
+}
+
+/// The outcome of a job, or of a check without one (e.g. when it's deleted by --clear-existing-checks).
+#[derive(Debug, Clone, Serialize)]
+pub struct Outcome {
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub context: Option<String>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub namespace: Option<String>,
+	pub name: String,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub check: Option<String>,
+	#[serde(flatten)]
+	pub action: Action,
+}
+
+/// Counts what happened in a single namespace, for --summary-only.
+#[derive(Debug, Default)]
+pub struct Tally {
+	created: usize,
+	updated: usize,
+	unchanged: usize,
+	paused: usize,
+	skipped: usize,
+	failed: usize,
+}
+
+impl Tally {
+	pub fn add(&mut self, action: &Action) {
+		match action {
+			Action::Created => self.created += 1,
+			Action::Updated => self.updated += 1,
+			Action::Unchanged => self.unchanged += 1,
+			Action::Paused => self.paused += 1,
+			Action::Skipped { .. } => self.skipped += 1,
+			Action::Failed { .. } => self.failed += 1,
+			Action::Deleted => {}
+		}
+	}
+}
+
+impl std::fmt::Display for Tally {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		write!(
+			f,
+			"created={} updated={} unchanged={} paused={} skipped={} failed={}",
+			self.created,
+			self.updated,
+			self.unchanged,
+			self.paused,
+			self.skipped,
+			self.failed,
+		)
+	}
+}
+
+/// Tallies what happened during a run, so it can be reported at the end (or when it's cut short).
+#[derive(Debug, Default, Serialize)]
+pub struct Summary {
+	created: usize,
+	updated: usize,
+	unchanged: usize,
+	deleted: usize,
+	paused: usize,
+	failed: usize,
+	empty_namespaces: usize,
+	#[serde(skip)]
+	skipped: BTreeMap<SkipReason, usize>,
+	outcomes: Vec<Outcome>,
+	/// Under --output ndjson, every outcome is printed as soon as it's recorded.
+	#[serde(skip)]
+	stream: bool,
+}
+
+impl Summary {
+	pub fn new(stream: bool) -> Self {
+		Summary {
+			stream,
+			..Default::default()
+		}
+	}
+
+	pub fn record(&mut self, outcome: Outcome) {
+		if self.stream {
+			// A consumer that went away shouldn't stop the synchronisation.
+			let _ = stream(&outcome, std::io::stdout().lock());
+		}
+
+		match &outcome.action {
+			Action::Created => self.created += 1,
+			Action::Updated => self.updated += 1,
+			Action::Unchanged => self.unchanged += 1,
+			Action::Deleted => self.deleted += 1,
+			Action::Paused => self.paused += 1,
+			Action::Skipped { reason } => *self.skipped.entry(*reason).or_default() += 1,
+			Action::Failed { .. } => self.failed += 1,
+		}
+		self.outcomes.push(outcome);
+	}
+
+	pub fn empty_namespace(&mut self) {
+		self.empty_namespaces += 1;
+	}
+
+	pub fn print(&self) {
+		let skipped: usize = self.skipped.values().sum();
+		let reasons = if skipped == 0 {
+			String::new()
+		} else {
+			let reasons = self.skipped.iter()
+				.map(|(reason, count)| format!("{}: {}", reason, count))
+				.collect::<Vec<_>>()
+				.join(", ");
+			format!(" ({})", reasons)
+		};
+
+		say!(
+			"Summary: created={} updated={} unchanged={} deleted={} paused={} failed={} skipped={}{} empty-namespaces={}",
+			self.created,
+			self.updated,
+			self.unchanged,
+			self.deleted,
+			self.paused,
+			self.failed,
+			skipped,
+			reasons,
+			self.empty_namespaces,
+		);
+	}
+}
+
+/// Writes the outcome as a single line of JSON, and flushes it, so a consumer sees it straight away.
+fn stream(outcome: &Outcome, mut output: impl Write) -> std::io::Result<()> {
+	let line = serde_json::to_string(outcome)?;
+	writeln!(output, "{}", line)?;
+	output.flush()
+}
+
+fn serialize_display<T: std::fmt::Display, S: serde::Serializer>(value: &T, serializer: S) -> Result<S::Ok, S::Error> {
+	serializer.collect_str(value)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn tallies_a_namespace_on_one_line() {
+		let mut tally = Tally::default();
+		for action in [Action::Created, Action::Created, Action::Updated, Action::Skipped { reason: SkipReason::Declined }] {
+			tally.add(&action);
+		}
+		assert_eq!(tally.to_string(), "created=2 updated=1 unchanged=0 paused=0 skipped=1 failed=0");
+	}
+
+	#[test]
+	fn streams_every_outcome_on_its_own_line() {
+		let outcome = |name: &str, action: Action| Outcome {
+			context: Some("prod".into()),
+			namespace: Some("billing".into()),
+			name: name.into(),
+			check: None,
+			action,
+		};
+		let mut output = vec![];
+		stream(&outcome("new-job", Action::Created), &mut output).unwrap();
+		stream(&outcome("broken-job", Action::Skipped { reason: SkipReason::InvalidSchedule }), &mut output).unwrap();
+
+		let records: Vec<serde_json::Value> = String::from_utf8(output).unwrap()
+			.lines()
+			.map(|line| serde_json::from_str(line).unwrap())
+			.collect();
+		assert_eq!(records, [
+			serde_json::json!({ "context": "prod", "namespace": "billing", "name": "new-job", "action": "created" }),
+			serde_json::json!({ "context": "prod", "namespace": "billing", "name": "broken-job", "action": "skipped", "reason": "invalid schedule" }),
+		]);
+	}
+}
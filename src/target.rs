@@ -0,0 +1,125 @@
+use healthchecks::model::NewCheck;
+
+/// Why a target's overrides were rejected.
+#[derive(Debug, thiserror::Error)]
+pub enum TargetError {
+	#[error("expected \"key=value\", found \"{0}\"")]
+	Malformed(String),
+
+	#[error("unknown override \"{0}\" (expected timeout, grace or tz)")]
+	UnknownKey(String),
+
+	#[error("{key} must be between {} and {} seconds, found \"{value}\"", SECONDS.start(), SECONDS.end())]
+	InvalidSeconds {
+		key: &'static str,
+		value: String,
+	},
+
+	#[error("unknown timezone \"{0}\"")]
+	UnknownTimezone(String),
+}
+
+/// The range that HealthChecks accepts for timeouts and grace periods.
+const SECONDS: std::ops::RangeInclusive<i32> = 60..=31_536_000;
+
+/// Check defaults that only apply to a single target, e.g. "context:namespace;timeout=300;grace=60;tz=America/New_York".
+#[derive(Debug, Default, Clone)]
+pub struct Overrides {
+	timeout: Option<i32>,
+	grace: Option<i32>,
+	tz: Option<String>,
+}
+
+impl Overrides {
+	pub fn parse(target: &str) -> Result<Self, TargetError> {
+		let mut overrides = Overrides::default();
+
+		for pair in target.split(';').skip(1) {
+			let Some((key, value)) = pair.split_once('=') else {
+				return Err(TargetError::Malformed(pair.into()));
+			};
+			if key.trim() == "tz" {
+				let tz = value.trim();
+				if !crate::plan::is_known_timezone(tz) {
+					return Err(TargetError::UnknownTimezone(tz.into()));
+				}
+				overrides.tz = Some(tz.into());
+				continue;
+			}
+			let (key, slot) = match key.trim() {
+				"timeout" => ("timeout", &mut overrides.timeout),
+				"grace" => ("grace", &mut overrides.grace),
+				key => return Err(TargetError::UnknownKey(key.into())),
+			};
+			let seconds = value.trim()
+				.parse()
+				.ok()
+				.filter(|seconds| SECONDS.contains(seconds))
+				.ok_or_else(|| TargetError::InvalidSeconds {
+					key,
+					value: value.into(),
+				})?;
+			*slot = Some(seconds);
+		}
+
+		Ok(overrides)
+	}
+
+	/// The default check, with these overrides applied.
+	pub fn apply(&self, default_check: &NewCheck) -> NewCheck {
+		NewCheck {
+			timeout: self.timeout.or(default_check.timeout),
+			grace: self.grace.or(default_check.grace),
+			tz: self.tz.clone().or_else(|| default_check.tz.clone()),
+			..default_check.clone()
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn parses_the_overrides_after_the_target() {
+		let overrides = Overrides::parse("prod:billing; timeout=300 ;grace = 60;tz=UTC").unwrap();
+		assert_eq!(overrides.timeout, Some(300));
+		assert_eq!(overrides.grace, Some(60));
+		assert_eq!(overrides.tz.as_deref(), Some("UTC"));
+
+		let overrides = Overrides::parse("prod:billing").unwrap();
+		assert_eq!((overrides.timeout, overrides.grace, overrides.tz), (None, None, None));
+	}
+
+	#[test]
+	fn rejects_malformed_overrides() {
+		assert!(matches!(Overrides::parse("prod;timeout"), Err(TargetError::Malformed(pair)) if pair == "timeout"));
+		assert!(matches!(Overrides::parse("prod;period=60"), Err(TargetError::UnknownKey(key)) if key == "period"));
+		assert!(matches!(Overrides::parse("prod;tz=../passwd"), Err(TargetError::UnknownTimezone(_))));
+	}
+
+	#[test]
+	fn rejects_seconds_out_of_range() {
+		for target in ["prod;timeout=59", "prod;grace=31536001", "prod;timeout=5m"] {
+			assert!(matches!(Overrides::parse(target), Err(TargetError::InvalidSeconds { .. })), "{}", target);
+		}
+		assert!(Overrides::parse("prod;timeout=60;grace=31536000").is_ok());
+	}
+
+	#[test]
+	fn falls_back_to_the_default_check() {
+		let default_check = NewCheck {
+			timeout: Some(3600),
+			grace: Some(900),
+			tz: Some("UTC".into()),
+			..Default::default()
+		};
+		let check = Overrides::parse("prod;grace=120").unwrap().apply(&default_check);
+		assert_eq!(check.timeout, Some(3600));
+		assert_eq!(check.grace, Some(120));
+		assert_eq!(check.tz.as_deref(), Some("UTC"));
+
+		let check = Overrides::parse("prod;tz=Europe/London").unwrap().apply(&default_check);
+		assert_eq!(check.tz.as_deref(), Some("Europe/London"));
+	}
+}
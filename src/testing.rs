@@ -0,0 +1,304 @@
+//! Helpers for the unit tests: parsing flags, and stand-in HTTP servers for HealthChecks and Kubernetes.
+
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+
+use clap::Parser;
+use healthchecks::model::{Check, NewCheck};
+use k8s_openapi::api::batch::v1::CronJob;
+
+use crate::Args;
+use crate::plan::{NamespacePlan, PlannedJob};
+
+/// Parses `flags` as though they were given on the command line.
+pub fn args(flags: &[&str]) -> Args {
+	Args::try_parse_from(std::iter::once("healthkube").chain(flags.iter().copied()))
+		.expect("valid flags")
+}
+
+/// A CronJob named `name`, running a single "app" container on `schedule`.
+pub fn cron_job(name: &str, schedule: &str) -> CronJob {
+	job(serde_json::json!({
+		"metadata": { "name": name, "namespace": "billing" },
+		"spec": {
+			"schedule": schedule,
+			"jobTemplate": {
+				"spec": {
+					"template": {
+						"spec": {
+							"containers": [{ "name": "app", "image": "registry.example.com/billing/app:1.0" }],
+						},
+					},
+				},
+			},
+		},
+	}))
+}
+
+/// A CronJob, as the API would return it.
+pub fn job(value: serde_json::Value) -> CronJob {
+	serde_json::from_value(value).expect("a valid CronJob")
+}
+
+/// The [cron_job] `name`, planned with a check of the same name and schedule.
+pub fn planned_job(name: &str) -> PlannedJob {
+	PlannedJob {
+		job: cron_job(name, "0 2 * * *"),
+		check: NewCheck {
+			name: Some(name.into()),
+			schedule: Some("0 2 * * *".into()),
+			..Default::default()
+		},
+	}
+}
+
+/// An empty plan for the "billing" namespace of the "prod" context.
+pub fn namespace_plan() -> NamespacePlan {
+	NamespacePlan {
+		context: "prod".into(),
+		namespace: "billing".into(),
+		api: None,
+		listed: 0,
+		terminating: false,
+		jobs: vec![],
+		skipped: vec![],
+		frequencies: vec![],
+	}
+}
+
+/// Points KUBECONFIG at a stand-in cluster, with the contexts "prod-a" and "prod-b", and "dev" (which can't be reached).
+///
+/// It has the namespaces "billing", "infra", "team-a" and "team-b", and a single CronJob: "nightly-backup-job" in "billing".
+pub fn cluster() -> &'static Server {
+	static CLUSTER: OnceLock<Server> = OnceLock::new();
+
+	CLUSTER.get_or_init(|| {
+		let server = Server::start(kubernetes);
+		let contexts: String = [("prod-a", "local"), ("prod-b", "local"), ("dev", "unreachable")].iter()
+			.map(|(name, cluster)| format!("- name: {}\n  context:\n    cluster: {}\n    user: local\n", name, cluster))
+			.collect();
+		let kubeconfig = format!(
+			"apiVersion: v1\nkind: Config\nclusters:\n- name: local\n  cluster:\n    server: {}\n- name: unreachable\n  cluster:\n    server: http://127.0.0.1:1\nusers:\n- name: local\n  user: {{}}\ncontexts:\n{}",
+			server.url,
+			contexts,
+		);
+		let path = std::env::temp_dir().join(format!("healthkube-test-{}.kubeconfig", std::process::id()));
+		std::fs::write(&path, kubeconfig).expect("a writable temp dir");
+		std::env::set_var("KUBECONFIG", path);
+		server
+	})
+}
+
+/// Answers like the Kubernetes API of [cluster].
+fn kubernetes(request: &Request) -> (u16, serde_json::Value) {
+	let path = request.path.split('?').next().unwrap_or_default();
+	let segments: Vec<_> = path.trim_matches('/').split('/').collect();
+	let list = |kind: &str, items: Vec<serde_json::Value>| serde_json::json!({
+		"apiVersion": "v1",
+		"kind": kind,
+		"metadata": {},
+		"items": items,
+	});
+
+	match segments.as_slice() {
+		["api", "v1", "namespaces"] => {
+			let namespaces = ["billing", "infra", "team-a", "team-b"].iter()
+				.map(|name| serde_json::json!({ "metadata": { "name": name } }))
+				.collect();
+			(200, list("NamespaceList", namespaces))
+		}
+		["api", "v1", "namespaces", name] => (200, serde_json::json!({ "metadata": { "name": name } })),
+		["apis", "batch", "v1", "namespaces", namespace, "cronjobs"] => {
+			let jobs = match *namespace {
+				"billing" => vec![serde_json::to_value(cron_job("nightly-backup-job", "0 2 * * *")).unwrap()],
+				_ => vec![],
+			};
+			(200, list("CronJobList", jobs))
+		}
+		["apis", "batch", "v1", "namespaces", _, "cronjobs", name] => {
+			(200, serde_json::to_value(cron_job(name, "0 2 * * *")).unwrap())
+		}
+		_ => (404, serde_json::json!({ "kind": "Status", "status": "Failure", "reason": "NotFound", "code": 404 })),
+	}
+}
+
+/// An existing check, whose ping URL (and so its id) ends in `id`.
+pub fn check(id: &str, name: &str, tags: &str) -> Check {
+	serde_json::from_value(serde_json::json!({
+		"name": name,
+		"tags": tags,
+		"desc": "",
+		"grace": 3600,
+		"n_pings": 0,
+		"status": "new",
+		"manual_resume": false,
+		"ping_url": format!("https://hc.example.com/ping/{}", id),
+	}))
+	.expect("a valid check")
+}
+
+/// A stand-in HealthChecks API, that already has the `checks`.
+///
+/// A created check is given the id "new-<n>", and updates only change the name and tags.
+/// Like HealthChecks, a check that's unique by name is updated in place of creating another.
+pub fn healthchecks(checks: Vec<Check>) -> Server {
+	let checks = Mutex::new(checks);
+	Server::start(move |request| {
+		let mut checks = checks.lock().unwrap();
+		let path = request.path.split('?').next().unwrap_or_default();
+		let segments: Vec<_> = path.split('/').filter(|segment| !segment.is_empty()).collect();
+		let body: serde_json::Value = serde_json::from_str(&request.body).unwrap_or_default();
+
+		match (request.method.as_str(), segments.as_slice()) {
+			("GET", ["checks"]) => (200, serde_json::json!({ "checks": *checks })),
+			("GET", ["channels"]) => (200, serde_json::json!({ "channels": [] })),
+			("POST", ["checks"]) => {
+				let name = body["name"].as_str().unwrap_or_default();
+				let unique_by_name = body["unique"].as_array().is_some_and(|unique| unique.iter().any(|field| field == "name"));
+				if let Some(found) = checks.iter_mut().find(|check| unique_by_name && check.name == name) {
+					if let Some(tags) = body["tags"].as_str() {
+						found.tags = tags.into();
+					}
+					return (200, serde_json::to_value(&*found).unwrap());
+				}
+
+				let id = format!("new-{}", checks.len());
+				let created = check(&id, name, body["tags"].as_str().unwrap_or_default());
+				checks.push(created.clone());
+				(201, serde_json::to_value(created).unwrap())
+			}
+			(method, ["checks", id, rest @ ..]) => {
+				let Some(found) = checks.iter_mut().find(|check| check.id().as_deref() == Some(*id)) else {
+					return (404, serde_json::json!({ "error": "not found" }));
+				};
+				if let ("POST", []) = (method, rest) {
+					if let Some(name) = body["name"].as_str() {
+						found.name = name.into();
+					}
+					if let Some(tags) = body["tags"].as_str() {
+						found.tags = tags.into();
+					}
+				}
+				(200, serde_json::to_value(&*found).unwrap())
+			}
+			_ => (404, serde_json::json!({ "error": "not found" })),
+		}
+	})
+}
+
+/// Writes the `jobs` to a fixture for --offline.
+pub fn fixture(jobs: &[CronJob]) -> PathBuf {
+	static WRITTEN: AtomicUsize = AtomicUsize::new(0);
+
+	let id = WRITTEN.fetch_add(1, Ordering::Relaxed);
+	let path = std::env::temp_dir().join(format!("healthkube-test-{}-{}.json", std::process::id(), id));
+	std::fs::write(&path, serde_json::to_vec(&serde_json::json!({ "items": jobs })).unwrap()).expect("a writable temp dir");
+	path
+}
+
+/// A request the [Server] received.
+#[derive(Debug, Clone)]
+pub struct Request {
+	pub method: String,
+	pub path: String,
+	/// With the names in lowercase.
+	pub headers: Vec<(String, String)>,
+	pub body: String,
+}
+
+impl Request {
+	pub fn header(&self, name: &str) -> Option<&str> {
+		self.headers.iter()
+			.find(|(header, _)| header.eq_ignore_ascii_case(name))
+			.map(|(_, value)| value.as_str())
+	}
+}
+
+/// A plain HTTP server on localhost, answering every request with whatever `handler` returns, and recording them.
+pub struct Server {
+	pub url: String,
+	requests: Arc<Mutex<Vec<Request>>>,
+}
+
+impl Server {
+	pub fn start(handler: impl Fn(&Request) -> (u16, serde_json::Value) + Send + 'static) -> Server {
+		let listener = TcpListener::bind("127.0.0.1:0").expect("a free port");
+		let url = format!("http://{}", listener.local_addr().expect("a bound address"));
+		let requests = Arc::new(Mutex::new(vec![]));
+
+		let recorded = requests.clone();
+		std::thread::spawn(move || {
+			for mut stream in listener.incoming().flatten() {
+				let Some(request) = read_request(&mut stream) else {
+					continue;
+				};
+				let (status, body) = handler(&request);
+				recorded.lock().unwrap().push(request);
+
+				let body = body.to_string();
+				let _ = write!(
+					stream,
+					"HTTP/1.1 {} Mock\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+					status,
+					body.len(),
+					body,
+				);
+			}
+		});
+
+		Server {
+			url,
+			requests,
+		}
+	}
+
+
+	/// A Kubernetes client for the server.
+	pub fn kube_client(&self) -> kube::Client {
+		let config = kube::Config::new(self.url.parse().expect("a valid url"));
+		kube::Client::try_from(config).expect("a kubernetes client")
+	}
+
+	/// Every request received so far, oldest first.
+	pub fn requests(&self) -> Vec<Request> {
+		self.requests.lock().unwrap().clone()
+	}
+}
+
+fn read_request(stream: &mut TcpStream) -> Option<Request> {
+	let mut reader = BufReader::new(stream);
+	let mut line = String::new();
+	reader.read_line(&mut line).ok()?;
+	let mut parts = line.split_whitespace();
+	let method = parts.next()?.to_string();
+	let path = parts.next()?.to_string();
+
+	let mut headers = vec![];
+	loop {
+		let mut line = String::new();
+		reader.read_line(&mut line).ok()?;
+		let line = line.trim_end();
+		if line.is_empty() {
+			break;
+		}
+		let (name, value) = line.split_once(':')?;
+		headers.push((name.trim().to_ascii_lowercase(), value.trim().to_string()));
+	}
+
+	let length = headers.iter()
+		.find(|(name, _)| name == "content-length")
+		.and_then(|(_, value)| value.parse().ok())
+		.unwrap_or(0);
+	let mut body = vec![0; length];
+	reader.read_exact(&mut body).ok()?;
+
+	Some(Request {
+		method,
+		path,
+		headers,
+		body: String::from_utf8_lossy(&body).into_owned(),
+	})
+}
@@ -0,0 +1,120 @@
+use std::collections::BTreeMap;
+use std::fmt::Display;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use healthchecks::manage::ManageClient;
+
+use crate::Args;
+use crate::output::say;
+
+/// The verbosity (-vvv) at which every HTTP call is logged.
+pub const TRACE_LEVEL: u8 = 3;
+
+/// How long each operation (e.g. "GET checks") took, across the whole run.
+static TIMINGS: Mutex<BTreeMap<String, Vec<Duration>>> = Mutex::new(BTreeMap::new());
+
+/// Runs a HealthChecks call, and logs it when tracing.
+///
+/// The client blocks, so the call is made off the runtime's thread, where it can't hold up a --run-timeout.
+pub async fn hc<T, E>(
+	args: &Args,
+	client: &ManageClient,
+	method: &str,
+	path: &str,
+	call: impl FnOnce(&ManageClient) -> Result<T, E> + Send + 'static,
+) -> Result<T, E>
+where
+	T: Send + 'static,
+	E: Display + Send + 'static,
+{
+	let started = Instant::now();
+	let client = client.clone();
+	let result = tokio::task::spawn_blocking(move || call(&client))
+		.await
+		.unwrap_or_else(|err| std::panic::resume_unwind(err.into_panic()));
+	let resource = path.split('/').next().unwrap_or_default();
+	let url = format!("{}/{}", args.hc.url.as_deref().unwrap_or_default(), path);
+	log(args, method, resource, &url, started, &result);
+	result
+}
+
+/// Records a finished HTTP call, and logs it when tracing.
+///
+/// Only the method, target and outcome are logged (never any bodies), and the line is redacted like any other output.
+pub fn log<T, E: Display>(args: &Args, method: &str, resource: &str, target: &str, started: Instant, result: &Result<T, E>) {
+	let elapsed = started.elapsed();
+	if let Ok(mut timings) = TIMINGS.lock() {
+		timings.entry(format!("{} {}", method, resource))
+			.or_default()
+			.push(elapsed);
+	}
+
+	if args.verbose < TRACE_LEVEL {
+		return;
+	}
+
+	let outcome = match result {
+		Ok(_) => String::from("ok"),
+		Err(err) => format!("error: {}", err),
+	};
+	let line = format!(
+		"Trace: {} {} -> {} ({} ms)",
+		method,
+		target,
+		outcome,
+		elapsed.as_millis(),
+	);
+	eprintln!("{}", crate::redact::redact(&line, &args.hc.secrets()));
+}
+
+/// Prints the count, total and percentiles of each operation, to show where a run spent its time.
+pub fn print_timings() {
+	let Ok(timings) = TIMINGS.lock() else {
+		return;
+	};
+	if timings.is_empty() {
+		return;
+	}
+
+	say!("Timings:");
+	for (operation, samples) in timings.iter() {
+		let mut samples = samples.clone();
+		samples.sort_unstable();
+		let total: Duration = samples.iter().sum();
+		say!(
+			"\t{: <20} count={} total={}ms p50={}ms p95={}ms",
+			operation,
+			samples.len(),
+			total.as_millis(),
+			percentile(&samples, 50).as_millis(),
+			percentile(&samples, 95).as_millis(),
+		);
+	}
+}
+
+/// The nearest-rank percentile of the sorted `samples`.
+fn percentile(samples: &[Duration], percentile: usize) -> Duration {
+	let rank = (samples.len() * percentile).div_ceil(100).max(1);
+	samples.get(rank - 1).copied().unwrap_or_default()
+}
+
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn picks_the_nearest_rank() {
+		let samples: Vec<_> = (1..=10).map(Duration::from_millis).collect();
+		assert_eq!(percentile(&samples, 50), Duration::from_millis(5));
+		assert_eq!(percentile(&samples, 95), Duration::from_millis(10));
+		assert_eq!(percentile(&samples, 100), Duration::from_millis(10));
+		assert_eq!(percentile(&samples, 0), Duration::from_millis(1));
+	}
+
+	#[test]
+	fn handles_no_samples() {
+		assert_eq!(percentile(&[], 50), Duration::ZERO);
+	}
+}
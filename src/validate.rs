@@ -0,0 +1,158 @@
+use anyhow::Result;
+use k8s_openapi::api::batch::v1::CronJob;
+use k8s_openapi::api::core::v1::Namespace;
+use kube::{Client, Config};
+use kube::api::ListParams;
+use kube::config::{Kubeconfig, KubeconfigError, KubeConfigOptions};
+
+use crate::Args;
+use crate::output::say;
+
+/// Performs read-only calls against HealthChecks and every target, and prints an OK/FAIL line for each.
+///
+/// Fails if any of them couldn't be reached.
+pub async fn validate(args: &Args, targets: &[String]) -> Result<()> {
+	validate_with(args, Kubeconfig::read(), targets).await
+}
+
+async fn validate_with(args: &Args, kubeconfig: Result<Kubeconfig, KubeconfigError>, targets: &[String]) -> Result<()> {
+	let mut failures = 0;
+	let secrets = args.hc.secrets();
+
+	let mut report = |name: &str, result: Result<()>| {
+		match result {
+			Ok(()) => say!("{: <50} OK", name),
+			Err(err) => {
+				failures += 1;
+				let message = crate::redact::redact(&format!("{:#}", err), &secrets);
+				say!("{: <50} FAIL ({})", name, message);
+			}
+		}
+	};
+
+	let result = match crate::hc_client(&args.hc) {
+		Ok(client) => crate::trace::hc(args, &client, "GET", "checks", |client| client.get_checks())
+			.await
+			.map(|_| ())
+			.map_err(anyhow::Error::from),
+		Err(err) => Err(err),
+	};
+	report("HealthChecks", result);
+
+	match kubeconfig {
+		Ok(kubeconfig) => {
+			let available: Vec<_> = kubeconfig.contexts.iter()
+				.map(|named| named.name.clone())
+				.collect();
+			let mut expanded = vec![];
+			for target in targets {
+				match crate::plan::expand_context(target, &available) {
+					Ok(matching) => expanded.extend(matching),
+					Err(err) => report(target, Err(err.into())),
+				}
+			}
+
+			for target in &expanded {
+				let (context, namespaces) = crate::split_target(target);
+
+				if !kubeconfig.contexts.iter().any(|named| named.name == context) {
+					report(target, Err(anyhow::anyhow!("Unknown context: {}", context)));
+					continue;
+				}
+
+				for namespace in namespaces.split(',') {
+					let name = format!("{}:{}", context, namespace);
+					let result = list_namespace(&kubeconfig, context, namespace).await;
+					report(&name, result);
+				}
+			}
+		}
+		Err(err) => report("Kubeconfig", Err(err.into())),
+	}
+
+	if failures > 0 {
+		anyhow::bail!("{} validation(s) failed", failures);
+	}
+
+	Ok(())
+}
+
+async fn list_namespace(kubeconfig: &Kubeconfig, context: &str, namespace: &str) -> Result<()> {
+	let opts = KubeConfigOptions {
+		context: Some(context.into()),
+		..Default::default()
+	};
+
+	let config = Config::from_custom_kubeconfig(kubeconfig.clone(), &opts).await?;
+	let client = Client::try_from(config)?;
+	let params = ListParams::default().limit(1);
+
+	if crate::plan::is_pattern(namespace) {
+		let api: kube::Api<Namespace> = kube::Api::all(client);
+		api.list(&params).await?;
+	} else {
+		let api: kube::Api<CronJob> = kube::Api::namespaced(client, namespace);
+		api.list(&params).await?;
+	}
+
+	Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::testing::{args, Server};
+
+	const KUBECONFIG: &str = r#"
+apiVersion: v1
+kind: Config
+clusters:
+- name: local
+  cluster:
+    server: http://127.0.0.1:1
+users:
+- name: local
+  user: {}
+contexts:
+- name: prod
+  context:
+    cluster: local
+    user: local
+"#;
+
+	fn healthchecks(status: u16) -> Server {
+		Server::start(move |_| (status, serde_json::json!({ "checks": [] })))
+	}
+
+	#[tokio::test]
+	async fn passes_with_valid_credentials() {
+		let server = healthchecks(200);
+		let args = args(&["--hc-key", "key", "--hc-url", &server.url, "prod"]);
+		let kubeconfig = Kubeconfig::from_yaml(KUBECONFIG);
+
+		validate_with(&args, kubeconfig, &[]).await.unwrap();
+		let requests = server.requests();
+		assert_eq!(requests.len(), 1);
+		assert_eq!((requests[0].method.as_str(), requests[0].path.as_str()), ("GET", "/checks"));
+	}
+
+	#[tokio::test]
+	async fn fails_with_invalid_credentials() {
+		let server = healthchecks(401);
+		let args = args(&["--hc-key", "key", "--hc-url", &server.url, "prod"]);
+		let kubeconfig = Kubeconfig::from_yaml(KUBECONFIG);
+
+		let err = validate_with(&args, kubeconfig, &[]).await.unwrap_err();
+		assert_eq!(err.to_string(), "1 validation(s) failed");
+	}
+
+	#[tokio::test]
+	async fn fails_on_a_missing_context() {
+		let server = healthchecks(200);
+		let args = args(&["--hc-key", "key", "--hc-url", &server.url, "prod"]);
+		let kubeconfig = Kubeconfig::from_yaml(KUBECONFIG);
+
+		let err = validate_with(&args, kubeconfig, &["staging:billing".to_string()]).await.unwrap_err();
+		assert_eq!(err.to_string(), "1 validation(s) failed");
+	}
+}